@@ -0,0 +1,21 @@
+use gemini_rs::prelude::*;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let api_key = std::env::var("GEMINI_API_KEY")?;
+
+    let gemini = GeminiClient::with_backend(ApiKey::new(api_key), Backend::AiStudio);
+
+    let prompt = vec![Content::builder()
+        .role(Role::User)
+        .add_text_part("What is the airspeed of an unladen swallow?")
+        .build()];
+
+    let request = GenerateContentRequest::builder().contents(prompt).build();
+    let response = gemini
+        .generate_content(&request, Model::GEMINI_2_0_FLASH_001)
+        .await?;
+    println!("Response: {:?}", response.candidates[0].get_text().unwrap());
+
+    Ok(())
+}
@@ -1,7 +1,7 @@
 use std::{error::Error, io::Cursor};
 
 use gemini_rs::prelude::{
-    GeminiClient, PersonGeneration, PredictImageRequest, PredictImageRequestParameters,
+    GeminiClient, Model, PersonGeneration, PredictImageRequest, PredictImageRequestParameters,
     PredictImageRequestParametersOutputOptions, PredictImageRequestPrompt,
     PredictImageSafetySetting,
 };
@@ -44,7 +44,7 @@ pub async fn main() -> Result<(), Box<dyn Error>> {
     println!("Request: {:#?}", serde_json::to_string(&request).unwrap());
 
     let mut result = gemini
-        .predict_image(&request, "imagen-3.0-fast-generate-001")
+        .predict_image(&request, Model::IMAGEN_3_0_FAST_GENERATE_001)
         .await?;
 
     let result = result.predictions.pop().unwrap();
@@ -35,7 +35,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let result = gemini
-        .generate_content(&request, "gemini-1.5-flash-002")
+        .generate_content(&request, Model::GEMINI_1_5_FLASH_002)
         .await?;
 
     println!("Response: {:?}", result.candidates[0].get_text().unwrap());
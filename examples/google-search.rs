@@ -35,7 +35,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     );
 
     let result = gemini
-        .generate_content(&request, "gemini-2.0-flash-001")
+        .generate_content(&request, Model::GEMINI_2_0_FLASH_001)
         .await?;
 
     println!("Response: {:?}", result.candidates[0].get_text().unwrap());
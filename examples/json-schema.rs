@@ -43,7 +43,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let result = gemini
-        .generate_content(&request, "gemini-1.5-flash-001")
+        .generate_content(&request, Model::GEMINI_1_5_FLASH_001)
         .await?;
 
     println!("Response: {:?}", result.candidates[0].get_text().unwrap());
@@ -34,7 +34,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("{}", serde_json::to_string_pretty(&request).unwrap());
 
     let result = gemini
-        .generate_content(&request, "gemini-1.0-pro-002")
+        .generate_content(&request, Model::GEMINI_1_0_PRO_002)
         .await?;
     println!("Response: {:?}", result);
 
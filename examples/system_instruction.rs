@@ -30,7 +30,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .build();
 
     let result = gemini
-        .generate_content(&request, "gemini-1.0-pro-002")
+        .generate_content(&request, Model::GEMINI_1_0_PRO_002)
         .await?;
 
     println!("Response: {:?}", result.candidates[0].get_text().unwrap());
@@ -30,7 +30,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let result = gemini
-        .text_embeddings(&embedding_request, "textembedding-gecko@003")
+        .text_embeddings(&embedding_request, Model::TEXTEMBEDDING_GECKO_003)
         .await?
         .into_result()?;
     println!("Response: {:?}", result);
@@ -22,12 +22,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let request = GenerateContentRequest::builder().contents(prompt).build();
 
-    let mut queue = gemini
-        .generate_content_stream(&request, "gemini-2.0-flash-001")
+    let mut stream = gemini
+        .generate_content_stream(&request, Model::GEMINI_2_0_FLASH_001)
         .await?;
 
-    while let Some(Ok(response)) = queue.next().await {
-        println!("Response: {:?}", response);
+    while let Some(response) = stream.next().await {
+        println!("Response: {:?}", response?);
     }
 
     Ok(())
@@ -0,0 +1,193 @@
+use reqwest::RequestBuilder;
+
+use crate::error::{Error, Result};
+use crate::model::{Model, ModelName};
+
+/// The host serving the Google AI Studio (Generative Language) API.
+pub static AI_STUDIO_ENDPOINT: &str = "generativelanguage.googleapis.com";
+
+/// The API version AI Studio serves every method from, `batchEmbedContents` included.
+const AI_STUDIO_API_VERSION: &str = "v1beta";
+
+/// The API surface a [`crate::prelude::GeminiClient`] talks to.
+///
+/// Both backends serve the same Gemini models and share the request and response types, but
+/// differ in URL shapes, authentication and a few response quirks, which the client takes care
+/// of.
+#[derive(Clone, Debug)]
+pub enum Backend {
+    /// Vertex AI on Google Cloud. Requests are authenticated with an OAuth bearer token.
+    VertexAi {
+        project_id: String,
+        location_id: String,
+    },
+    /// Google AI Studio (the Generative Language API). Requests are authenticated with an API
+    /// key, sent in the `x-goog-api-key` header. Use with [`crate::prelude::ApiKey`].
+    AiStudio,
+    /// Vertex AI in express mode, without a Google Cloud project. Requests are authenticated
+    /// with a Vertex AI API key, sent in the `x-goog-api-key` header. Use with
+    /// [`crate::prelude::ApiKey`].
+    VertexAiExpress,
+}
+
+impl Backend {
+    /// The base URL of the public endpoint serving this backend. For Vertex AI this is the
+    /// regional endpoint for the location, or `https://aiplatform.googleapis.com` for the
+    /// `global` location.
+    pub fn default_base_url(&self) -> String {
+        match self {
+            Backend::VertexAi { location_id, .. } if location_id == "global" => {
+                "https://aiplatform.googleapis.com".to_string()
+            }
+            Backend::VertexAi { location_id, .. } => {
+                format!("https://{}-aiplatform.googleapis.com", location_id)
+            }
+            Backend::AiStudio => format!("https://{}", AI_STUDIO_ENDPOINT),
+            Backend::VertexAiExpress => "https://aiplatform.googleapis.com".to_string(),
+        }
+    }
+}
+
+/// Where and how the client sends its requests: the backend plus the URL settings from
+/// [`crate::prelude::GeminiClientBuilder`].
+#[derive(Clone, Debug)]
+pub(crate) struct Endpoint {
+    pub backend: Backend,
+    /// Overrides [`Backend::default_base_url`].
+    pub base_url: Option<String>,
+    pub api_version: Option<String>,
+    pub publisher: String,
+}
+
+impl Endpoint {
+    pub fn new(backend: Backend) -> Self {
+        Endpoint {
+            backend,
+            base_url: None,
+            api_version: None,
+            publisher: "google".to_string(),
+        }
+    }
+
+    /// The same endpoint for another Vertex AI location, or `None` for other backends. A custom
+    /// base URL is kept as is, otherwise the regional endpoint for the location is used.
+    pub fn with_location(&self, location: &str) -> Option<Endpoint> {
+        let Backend::VertexAi { project_id, .. } = &self.backend else {
+            return None;
+        };
+        Some(Endpoint {
+            backend: Backend::VertexAi {
+                project_id: project_id.clone(),
+                location_id: location.to_string(),
+            },
+            ..self.clone()
+        })
+    }
+
+    pub fn location(&self) -> Option<&str> {
+        match &self.backend {
+            Backend::VertexAi { location_id, .. } => Some(location_id),
+            Backend::AiStudio | Backend::VertexAiExpress => None,
+        }
+    }
+
+    pub fn generate_content_url(&self, model: &Model) -> String {
+        self.model_url("v1beta1", model, "generateContent")
+    }
+
+    pub fn stream_generate_content_url(&self, model: &Model) -> String {
+        self.model_url("v1beta1", model, "streamGenerateContent?alt=sse")
+    }
+
+    pub fn count_tokens_url(&self, model: &Model) -> String {
+        self.model_url("v1beta1", model, "countTokens")
+    }
+
+    pub fn text_embeddings_url(&self, model: &Model) -> String {
+        match self.backend {
+            Backend::VertexAi { .. } | Backend::VertexAiExpress => {
+                self.model_url("v1", model, "predict")
+            }
+            Backend::AiStudio => self.model_url(AI_STUDIO_API_VERSION, model, "batchEmbedContents"),
+        }
+    }
+
+    pub fn predict_url(&self, model: &Model) -> String {
+        self.model_url("v1", model, "predict")
+    }
+
+    /// Attaches the credential returned by the token provider to the request, using the header
+    /// expected by the backend.
+    pub fn authorize(&self, request: RequestBuilder, token: &str) -> RequestBuilder {
+        match self.backend {
+            Backend::VertexAi { .. } => request.bearer_auth(token),
+            Backend::AiStudio | Backend::VertexAiExpress => request.header("x-goog-api-key", token),
+        }
+    }
+
+    /// Fails with [`Error::UnsupportedByModel`] for models the backend doesn't serve: publisher
+    /// models and endpoints only exist on Vertex AI with a project.
+    pub fn check_model(&self, model: &Model) -> Result<()> {
+        match (&self.backend, model.name()) {
+            (
+                Backend::AiStudio | Backend::VertexAiExpress,
+                ModelName::Publisher { .. } | ModelName::Endpoint(_),
+            ) => Err(Error::UnsupportedByModel(format!(
+                "{}: only available on Vertex AI with a project",
+                model
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    fn model_url(&self, default_version: &str, model: &Model, method: &str) -> String {
+        let base_url = self
+            .base_url
+            .clone()
+            .unwrap_or_else(|| self.backend.default_base_url());
+        // AI Studio has no publishers, and ignores the default version of the method.
+        let version = self.api_version.as_deref().unwrap_or(match self.backend {
+            Backend::VertexAi { .. } | Backend::VertexAiExpress => default_version,
+            Backend::AiStudio => AI_STUDIO_API_VERSION,
+        });
+        let (project_id, location_id) = match &self.backend {
+            Backend::VertexAi {
+                project_id,
+                location_id,
+            } => (project_id, location_id),
+            Backend::AiStudio => match model.name() {
+                ModelName::Id(id) => {
+                    return format!("{}/{}/models/{}:{}", base_url, version, id, method)
+                }
+                // Publisher models and endpoints are rejected by `check_model`.
+                _ => return format!("{}/{}/{}:{}", base_url, version, model, method),
+            },
+            // Express mode has no projects or locations.
+            Backend::VertexAiExpress => match model.name() {
+                ModelName::Id(id) => {
+                    return format!(
+                        "{}/{}/publishers/{}/models/{}:{}",
+                        base_url, version, self.publisher, id, method
+                    )
+                }
+                _ => return format!("{}/{}/{}:{}", base_url, version, model, method),
+            },
+        };
+        let location_url = format!(
+            "{}/{}/projects/{}/locations/{}",
+            base_url, version, project_id, location_id
+        );
+        match model.name() {
+            ModelName::Id(id) => format!(
+                "{}/publishers/{}/models/{}:{}",
+                location_url, self.publisher, id, method
+            ),
+            ModelName::Publisher { .. } | ModelName::Endpoint(_) => {
+                format!("{}/{}:{}", location_url, model, method)
+            }
+            ModelName::ResourceName(name) => {
+                format!("{}/{}/{}:{}", base_url, version, name, method)
+            }
+        }
+    }
+}
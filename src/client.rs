@@ -1,81 +1,226 @@
-use crate::error::Result as GeminiResult;
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use std::vec;
+use tokio::time::Instant;
 use tokio_stream::{Stream, StreamExt};
 
-use deadqueue::unlimited::Queue;
-use reqwest_eventsource::{Event, EventSource};
+use eventsource_stream::Eventsource;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, USER_AGENT};
+use tower::{BoxError, Service};
 use tracing::error;
 
+use crate::backend::{Backend, Endpoint};
 use crate::dialogue::Message;
-use crate::error::{Error, Result};
+use crate::error::{Error, HttpStatusError, Result};
+use crate::failover::Failover;
+use crate::generative::BoxStream;
+use crate::model::{Model, ModelName};
 use crate::prelude::{
     Candidate, Content, CountTokensRequest, CountTokensResponse, GenerateContentRequest,
     GenerateContentResponse, GenerateContentResponseResult, TextEmbeddingRequest,
     TextEmbeddingResponse,
 };
-use crate::types::{PredictImageRequest, PredictImageResponse, Role};
+use crate::rate_limit::{estimate_tokens, RateLimiter};
+use crate::request_options::RequestOptions;
+use crate::retry::{self, RetryPolicy};
+use crate::service::HttpService;
+use crate::stream::{FinishedCandidates, Reopen, ResponseStream};
+use crate::timeout::{with_deadline, TimeoutKind, TimeoutStream, Timeouts};
+use crate::types::{
+    AiStudioCountTokensRequest, BatchEmbedContentsRequest, BatchEmbedContentsResponse,
+    PredictImageRequest, PredictImageResponse, Role,
+};
+use crate::usage::UsageTracker;
 use crate::{prelude::Part, token_provider::TokenProvider};
 
+/// The OAuth scopes requested by default.
 pub static AUTH_SCOPE: &[&str] = &["https://www.googleapis.com/auth/cloud-platform"];
 
-#[derive(Clone, Debug)]
-pub struct GeminiClient<T: TokenProvider + Clone> {
+const DEFAULT_FAILOVER_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// A client for the Gemini API.
+///
+/// The client is cheap to clone: clones share the same HTTP client, token provider and state,
+/// such as the rate limiter. It is `Send` and `Sync` when the token provider is, so it can be
+/// shared between tasks, e.g. as state of a web server.
+#[derive(Debug)]
+pub struct GeminiClient<T: TokenProvider> {
+    inner: Arc<ClientInner<T>>,
+}
+
+impl<T: TokenProvider> Clone for GeminiClient<T> {
+    fn clone(&self) -> Self {
+        GeminiClient {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ClientInner<T: TokenProvider> {
     token_provider: T,
     client: reqwest::Client,
-    api_endpoint: String,
-    project_id: String,
-    location_id: String,
+    service: HttpService,
+    endpoint: Endpoint,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    usage_tracker: Option<UsageTracker>,
+    timeouts: Timeouts,
+    failover: Option<Failover>,
+    headers: HeaderMap,
+    labels: HashMap<String, String>,
+    user_project: Option<String>,
+    user_agent_suffix: Option<String>,
+    scopes: Vec<String>,
 }
 
-unsafe impl<T: TokenProvider + Clone> Send for GeminiClient<T> {}
-unsafe impl<T: TokenProvider + Clone> Sync for GeminiClient<T> {}
-
-impl<T: TokenProvider + Clone> GeminiClient<T> {
+impl<T: TokenProvider> GeminiClient<T> {
     pub fn new(
         token_provider: T,
         api_endpoint: String,
         project_id: String,
         location_id: String,
     ) -> Self {
-        GeminiClient {
-            token_provider,
-            client: reqwest::Client::new(),
-            api_endpoint,
+        let backend = Backend::VertexAi {
             project_id,
             location_id,
-        }
+        };
+        GeminiClient::builder(token_provider, backend)
+            .base_url(format!("https://{}", api_endpoint))
+            .build()
+    }
+
+    /// Creates a client for the given backend, using its default endpoint. For
+    /// [`Backend::AiStudio`] and [`Backend::VertexAiExpress`], the token provider is expected
+    /// to return an API key, such as [`crate::prelude::ApiKey`].
+    pub fn with_backend(token_provider: T, backend: Backend) -> Self {
+        GeminiClient::builder(token_provider, backend).build()
     }
 
+    pub fn builder(token_provider: T, backend: Backend) -> GeminiClientBuilder<T> {
+        GeminiClientBuilder::new(token_provider, backend)
+    }
+
+    /// Streams the response as it is generated. See [`ResponseStream`] for how the stream ends
+    /// and reports errors.
     pub async fn generate_content_stream(
         &self,
         request: &GenerateContentRequest,
-        model: &str,
-    ) -> Result<impl Stream<Item = GeminiResult<GenerateContentResponseResult>>> {
-        let access_token = self.token_provider.get_token(AUTH_SCOPE).await.unwrap();
-        let endpoint_url = format!(
-            "https://{}/v1beta1/projects/{}/locations/{}/publishers/google/models/{}:streamGenerateContent?alt=sse", self.api_endpoint, self.project_id, self.location_id, model,
-        );
-        let client = self.client.clone();
-        let request = request.clone();
-        let req = client
-            .post(&endpoint_url)
-            .bearer_auth(access_token)
-            .json(&request);
+        model: impl Into<Model>,
+    ) -> Result<ResponseStream> {
+        self.generate_content_stream_with_options(request, model, &RequestOptions::default())
+            .await
+    }
 
-        let event_source = EventSource::new(req).unwrap();
+    pub async fn generate_content_stream_with_options(
+        &self,
+        request: &GenerateContentRequest,
+        model: impl Into<Model>,
+        options: &RequestOptions,
+    ) -> Result<ResponseStream> {
+        let model = model.into();
+        let started = Instant::now();
+        let timeouts = self.timeouts(options);
+        let stream_setup = self.event_stream(request, &model, options);
+        let stream = with_deadline(timeouts.first_event_deadline(started), stream_setup).await?;
+        let stream: BoxStream<'static, GenerateContentResponseResult> = Box::pin(stream);
+        Ok(ResponseStream::new(TimeoutStream::new(
+            stream, started, &timeouts,
+        )))
+    }
 
-        let mapped = event_source.filter_map(|event| {
-            let event = match event {
-                Ok(event) => event,
-                Err(reqwest_eventsource::Error::StreamEnded) => {
-                    return Some(Err(Error::EventSourceClosedError))
+    /// Streams the response like [`GeminiClient::generate_content_stream_with_options`],
+    /// reconnecting up to `max_resumes` times when the connection drops.
+    ///
+    /// The new request has the text received so far appended as a model turn, so the model
+    /// continues its answer, and the stream goes on with the responses of the new request. Only
+    /// the text of the first candidate is carried over, so this is meant for requests of a
+    /// single candidate without function calls.
+    ///
+    /// The total timeout applies to the whole call, reconnections included: once it has expired,
+    /// the stream ends with [`Error::Timeout`] instead of resuming.
+    pub async fn generate_content_stream_resumable(
+        &self,
+        request: &GenerateContentRequest,
+        model: impl Into<Model>,
+        options: &RequestOptions,
+        max_resumes: u32,
+    ) -> Result<ResponseStream>
+    where
+        T: Send + Sync + 'static,
+    {
+        let model = model.into();
+        let timeouts = self.timeouts(options);
+        let total_deadline = timeouts.total.map(|total| Instant::now() + total);
+        let stream = self
+            .generate_content_stream_with_options(request, model.clone(), options)
+            .await?;
+
+        let client = self.clone();
+        let request = request.clone();
+        let options = options.clone();
+        let reopen: Reopen = Box::new(move |text: String| {
+            let client = client.clone();
+            let mut request = request.clone();
+            let model = model.clone();
+            let mut options = options.clone();
+            // The reopened stream only gets the time left from the total timeout.
+            if let Some(deadline) = total_deadline {
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining.is_zero() {
+                    return Box::pin(async { Err(Error::Timeout(TimeoutKind::Total)) });
                 }
-                Err(e) => return Some(Err(e.into())),
-            };
+                options.timeouts = Some(Timeouts {
+                    total: Some(remaining),
+                    ..timeouts
+                });
+            }
+            if !text.is_empty() {
+                request.contents.push(
+                    Content::builder()
+                        .role(Role::Model)
+                        .add_text_part(text)
+                        .build(),
+                );
+            }
+            Box::pin(async move {
+                client
+                    .generate_content_stream_with_options(&request, model, &options)
+                    .await
+            })
+        });
+        Ok(stream.resumable(max_resumes, reopen))
+    }
+
+    /// Opens a `streamGenerateContent` request and maps its server-sent events into responses.
+    async fn event_stream(
+        &self,
+        request: &GenerateContentRequest,
+        model: &Model,
+        options: &RequestOptions,
+    ) -> Result<impl Stream<Item = Result<GenerateContentResponseResult>> + Send + 'static> {
+        model.validate(request)?;
+        let access_token = self.get_token(options).await?;
+        let estimated_tokens = estimate_tokens(request);
+        self.acquire_rate_limit(model, estimated_tokens).await;
+        let request = self.with_labels(request, options);
+
+        let resp = self
+            .send(model, options, |endpoint| {
+                let endpoint_url = endpoint.stream_generate_content_url(model);
+                endpoint
+                    .authorize(self.inner.client.post(&endpoint_url), &access_token)
+                    .json(&request)
+            })
+            .await?;
 
-            let Event::Message(event_message) = event else {
-                return None;
+        let mut usage_recorder = self.usage_recorder(model, options, estimated_tokens);
+        let mapped = resp.bytes_stream().eventsource().filter_map(move |event| {
+            let event_message = match event {
+                Ok(event) => event,
+                Err(e) => return Some(Err(reqwest_eventsource::Error::from(e).into())),
             };
 
             let gemini_response: GenerateContentResponse =
@@ -89,116 +234,67 @@ impl<T: TokenProvider + Clone> GeminiClient<T> {
                 Err(e) => return Some(Err(e)),
             };
 
+            usage_recorder.record(&gemini_response);
             Some(Ok(gemini_response))
         });
         Ok(mapped)
     }
 
-    pub async fn stream_generate_content(
+    pub async fn generate_content(
         &self,
         request: &GenerateContentRequest,
-        model: &str,
-    ) -> Arc<Queue<Option<Result<GenerateContentResponseResult>>>> {
-        let queue = Arc::new(Queue::<Option<Result<GenerateContentResponseResult>>>::new());
-        let access_token = match self.token_provider.get_token(AUTH_SCOPE).await {
-            Ok(access_token) => access_token,
-            Err(e) => {
-                queue.push(Some(Err(e)));
-                return queue;
-            }
-        };
-
-        // Clone the queue and other necessary data to move into the async block.
-        let cloned_queue = queue.clone();
-        let endpoint_url: String = format!(
-            "https://{}/v1beta1/projects/{}/locations/{}/publishers/google/models/{}:streamGenerateContent?alt=sse", self.api_endpoint, self.project_id, self.location_id, model,
-        );
-        let client = self.client.clone();
-        let request = request.clone();
-
-        // Start a thread to run the request in the background.
-        tokio::spawn(async move {
-            let req = client
-                .post(&endpoint_url)
-                .bearer_auth(access_token)
-                .json(&request);
-
-            let mut event_source = match EventSource::new(req) {
-                Ok(event_source) => event_source,
-                Err(e) => {
-                    cloned_queue.push(Some(Err(e.into())));
-                    return;
-                }
-            };
-            while let Some(event) = event_source.next().await {
-                match event {
-                    Ok(event) => {
-                        if let Event::Message(event) = event {
-                            let response: serde_json::error::Result<GenerateContentResponse> =
-                                serde_json::from_str(&event.data);
-
-                            match response {
-                                Ok(response) => {
-                                    let result = response.into_result();
-                                    let finished = match &result {
-                                        Ok(result) => result.candidates[0].finish_reason.is_some(),
-                                        Err(_) => true,
-                                    };
-                                    cloned_queue.push(Some(result));
-                                    if finished {
-                                        break;
-                                    }
-                                }
-                                Err(_) => {
-                                    tracing::error!("Error parsing message: {}", event.data);
-                                    break;
-                                }
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        tracing::error!("Error in event source: {:?}", e);
-                        break;
-                    }
-                }
-            }
-            cloned_queue.push(None);
-        });
-
-        // Return the queue that will receive the responses.
-        queue
+        model: impl Into<Model>,
+    ) -> Result<GenerateContentResponseResult> {
+        self.generate_content_with_options(request, model, &RequestOptions::default())
+            .await
     }
 
-    pub async fn generate_content(
+    pub async fn generate_content_with_options(
         &self,
         request: &GenerateContentRequest,
-        model: &str,
+        model: impl Into<Model>,
+        options: &RequestOptions,
     ) -> Result<GenerateContentResponseResult> {
-        let access_token = self.token_provider.get_token(AUTH_SCOPE).await?;
-        let endpoint_url: String = format!(
-            "https://{}/v1beta1/projects/{}/locations/{}/publishers/google/models/{}:generateContent", self.api_endpoint, self.project_id, self.location_id, model,
-        );
-        let resp = self
-            .client
-            .post(&endpoint_url)
-            .bearer_auth(access_token)
-            .json(&request)
-            .send()
-            .await?;
+        let model = model.into();
+        with_deadline(self.deadline(options), async {
+            model.validate(request)?;
+            let access_token = self.get_token(options).await?;
+            let estimated_tokens = estimate_tokens(request);
+            self.acquire_rate_limit(&model, estimated_tokens).await;
+            let request = self.with_labels(request, options);
+            let resp = self
+                .send(&model, options, |endpoint| {
+                    let endpoint_url = endpoint.generate_content_url(&model);
+                    endpoint
+                        .authorize(self.inner.client.post(&endpoint_url), &access_token)
+                        .json(&request)
+                })
+                .await?;
 
-        let txt_json = resp.text().await?;
-        tracing::debug!("generate_content response: {:?}", txt_json);
-        match serde_json::from_str::<GenerateContentResponse>(&txt_json) {
-            Ok(response) => Ok(response.into_result()?),
-            Err(e) => {
-                tracing::error!("Failed to parse response: {} with error {}", txt_json, e);
-                Err(e.into())
+            let txt_json = resp.text().await?;
+            tracing::debug!("generate_content response: {:?}", txt_json);
+            match serde_json::from_str::<GenerateContentResponse>(&txt_json) {
+                Ok(response) => {
+                    let response = response.into_result()?;
+                    self.usage_recorder(&model, options, estimated_tokens)
+                        .record(&response);
+                    Ok(response)
+                }
+                Err(e) => {
+                    tracing::error!("Failed to parse response: {} with error {}", txt_json, e);
+                    Err(e.into())
+                }
             }
-        }
+        })
+        .await
     }
 
     /// Prompts a conversation to the model.
-    pub async fn prompt_conversation(&self, messages: &[Message], model: &str) -> Result<Message> {
+    pub async fn prompt_conversation(
+        &self,
+        messages: &[Message],
+        model: impl Into<Model>,
+    ) -> Result<Message> {
         let request = GenerateContentRequest {
             contents: messages
                 .iter()
@@ -211,6 +307,7 @@ impl<T: TokenProvider + Clone> GeminiClient<T> {
             tools: None,
             system_instruction: None,
             safety_settings: None,
+            labels: None,
         };
 
         let response = self.generate_content(&request, model).await?;
@@ -235,75 +332,1257 @@ impl<T: TokenProvider + Clone> GeminiClient<T> {
     pub async fn text_embeddings(
         &self,
         request: &TextEmbeddingRequest,
-        model: &str,
+        model: impl Into<Model>,
     ) -> Result<TextEmbeddingResponse> {
-        let endpoint_url = format!(
-            "https://{}/v1/projects/{}/locations/{}/publishers/google/models/{}:predict",
-            self.api_endpoint, self.project_id, self.location_id, model,
-        );
-        let access_token = self.token_provider.get_token(AUTH_SCOPE).await?;
-        let resp = self
-            .client
-            .post(&endpoint_url)
-            .bearer_auth(access_token)
-            .json(&request)
-            .send()
-            .await?;
-        let txt_json = resp.text().await?;
-        tracing::debug!("text_embeddings response: {:?}", txt_json);
-        Ok(serde_json::from_str::<TextEmbeddingResponse>(&txt_json)?)
+        self.text_embeddings_with_options(request, model, &RequestOptions::default())
+            .await
+    }
+
+    pub async fn text_embeddings_with_options(
+        &self,
+        request: &TextEmbeddingRequest,
+        model: impl Into<Model>,
+        options: &RequestOptions,
+    ) -> Result<TextEmbeddingResponse> {
+        let model = model.into();
+        with_deadline(self.deadline(options), async {
+            let access_token = self.get_token(options).await?;
+            self.acquire_rate_limit(&model, 0).await;
+            let ai_studio_request = match self.inner.endpoint.backend {
+                Backend::VertexAi { .. } | Backend::VertexAiExpress => None,
+                Backend::AiStudio => Some(BatchEmbedContentsRequest::new(request, &model)),
+            };
+            let resp = self
+                .send(&model, options, |endpoint| {
+                    let endpoint_url = endpoint.text_embeddings_url(&model);
+                    let req =
+                        endpoint.authorize(self.inner.client.post(&endpoint_url), &access_token);
+                    match &ai_studio_request {
+                        None => req.json(&request),
+                        Some(ai_studio_request) => req.json(ai_studio_request),
+                    }
+                })
+                .await?;
+            let txt_json = resp.text().await?;
+            tracing::debug!("text_embeddings response: {:?}", txt_json);
+            match self.inner.endpoint.backend {
+                Backend::VertexAi { .. } | Backend::VertexAiExpress => {
+                    Ok(serde_json::from_str::<TextEmbeddingResponse>(&txt_json)?)
+                }
+                Backend::AiStudio => {
+                    Ok(serde_json::from_str::<BatchEmbedContentsResponse>(&txt_json)?.into())
+                }
+            }
+        })
+        .await
     }
 
     pub async fn count_tokens(
         &self,
         request: &CountTokensRequest,
-        model: &str,
+        model: impl Into<Model>,
     ) -> Result<CountTokensResponse> {
-        let endpoint_url = format!(
-            "https://{}/v1beta1/projects/{}/locations/{}/publishers/google/models/{}:countTokens",
-            self.api_endpoint, self.project_id, self.location_id, model,
-        );
-        let access_token = self.token_provider.get_token(AUTH_SCOPE).await?;
-        let resp = self
-            .client
-            .post(&endpoint_url)
-            .bearer_auth(access_token)
-            .json(&request)
-            .send()
-            .await?;
+        self.count_tokens_with_options(request, model, &RequestOptions::default())
+            .await
+    }
+
+    pub async fn count_tokens_with_options(
+        &self,
+        request: &CountTokensRequest,
+        model: impl Into<Model>,
+        options: &RequestOptions,
+    ) -> Result<CountTokensResponse> {
+        let model = model.into();
+        with_deadline(self.deadline(options), async {
+            let access_token = self.get_token(options).await?;
+            let resp = self
+                .send(&model, options, |endpoint| {
+                    let endpoint_url = endpoint.count_tokens_url(&model);
+                    let req =
+                        endpoint.authorize(self.inner.client.post(&endpoint_url), &access_token);
+                    match endpoint.backend {
+                        Backend::VertexAi { .. } | Backend::VertexAiExpress => req.json(&request),
+                        Backend::AiStudio => req.json(&AiStudioCountTokensRequest::from(request)),
+                    }
+                })
+                .await?;
 
-        let txt_json = resp.text().await?;
-        tracing::debug!("count_tokens response: {:?}", txt_json);
-        Ok(serde_json::from_str(&txt_json)?)
+            let txt_json = resp.text().await?;
+            tracing::debug!("count_tokens response: {:?}", txt_json);
+            Ok(serde_json::from_str(&txt_json)?)
+        })
+        .await
     }
 
     pub async fn predict_image(
         &self,
         request: &PredictImageRequest,
-        model: &str,
+        model: impl Into<Model>,
+    ) -> Result<PredictImageResponse> {
+        self.predict_image_with_options(request, model, &RequestOptions::default())
+            .await
+    }
+
+    pub async fn predict_image_with_options(
+        &self,
+        request: &PredictImageRequest,
+        model: impl Into<Model>,
+        options: &RequestOptions,
     ) -> Result<PredictImageResponse> {
-        let endpoint_url = format!(
-            "https://{}/v1/projects/{}/locations/{}/publishers/google/models/{}:predict",
-            self.api_endpoint, self.project_id, self.location_id, model,
+        let model = model.into();
+        with_deadline(self.deadline(options), async {
+            let access_token = self.get_token(options).await?;
+            self.acquire_rate_limit(&model, 0).await;
+            let resp = self
+                .send(&model, options, |endpoint| {
+                    let endpoint_url = endpoint.predict_url(&model);
+                    endpoint
+                        .authorize(self.inner.client.post(&endpoint_url), &access_token)
+                        .json(&request)
+                })
+                .await?;
+
+            let txt_json = resp.text().await?;
+
+            match serde_json::from_str::<PredictImageResponse>(&txt_json) {
+                Ok(response) => Ok(response),
+                Err(e) => {
+                    error!(response = txt_json, error = ?e, "Failed to parse response");
+                    Err(e.into())
+                }
+            }
+        })
+        .await
+    }
+
+    fn timeouts(&self, options: &RequestOptions) -> Timeouts {
+        match options.timeouts {
+            Some(timeouts) => timeouts.or(self.inner.timeouts),
+            None => self.inner.timeouts,
+        }
+    }
+
+    /// The deadline of a call with the given options, starting now.
+    fn deadline(&self, options: &RequestOptions) -> Option<(Instant, TimeoutKind)> {
+        let total = self.timeouts(options).total?;
+        Some((Instant::now() + total, TimeoutKind::Total))
+    }
+
+    /// Gets a token for the scopes in effect for the call.
+    async fn get_token(&self, options: &RequestOptions) -> Result<String> {
+        let scopes = options.scopes.as_ref().unwrap_or(&self.inner.scopes);
+        let scopes: Vec<&str> = scopes.iter().map(String::as_str).collect();
+        self.inner.token_provider.get_token(&scopes).await
+    }
+
+    /// Adds the headers configured on the client and for the call to a request.
+    fn decorate(
+        &self,
+        request: reqwest::RequestBuilder,
+        options: &RequestOptions,
+    ) -> reqwest::RequestBuilder {
+        let mut request = request
+            .headers(self.inner.headers.clone())
+            .headers(options.headers.clone());
+        let user_project = options.user_project.as_ref();
+        if let Some(user_project) = user_project.or(self.inner.user_project.as_ref()) {
+            request = request.header("x-goog-user-project", user_project);
+        }
+        if let Some(suffix) = &self.inner.user_agent_suffix {
+            let user_agent = format!("gemini-rs/{} {}", env!("CARGO_PKG_VERSION"), suffix);
+            request = request.header(USER_AGENT, user_agent);
+        }
+        request
+    }
+
+    /// Adds the labels configured on the client and for the call to a content generation
+    /// request. Labels already set on the request take precedence. AI Studio doesn't support
+    /// labels, so requests to it are left as is.
+    fn with_labels<'a>(
+        &self,
+        request: &'a GenerateContentRequest,
+        options: &RequestOptions,
+    ) -> Cow<'a, GenerateContentRequest> {
+        let no_labels = self.inner.labels.is_empty() && options.labels.is_empty();
+        if no_labels || matches!(self.inner.endpoint.backend, Backend::AiStudio) {
+            return Cow::Borrowed(request);
+        }
+        let mut labels = self.inner.labels.clone();
+        labels.extend(options.labels.clone());
+        labels.extend(request.labels.clone().unwrap_or_default());
+        let mut request = request.clone();
+        request.labels = Some(labels);
+        Cow::Owned(request)
+    }
+
+    fn usage_recorder(
+        &self,
+        model: &Model,
+        options: &RequestOptions,
+        estimated_tokens: u32,
+    ) -> UsageRecorder {
+        UsageRecorder {
+            rate_limiter: self.inner.rate_limiter.clone(),
+            usage_tracker: self.inner.usage_tracker.clone(),
+            model: model.to_string(),
+            tag: options.usage_tag.clone(),
+            estimated_tokens,
+            finished: FinishedCandidates::default(),
+        }
+    }
+
+    async fn acquire_rate_limit(&self, model: &Model, estimated_tokens: u32) {
+        if let Some(rate_limiter) = &self.inner.rate_limiter {
+            rate_limiter
+                .acquire(&model.to_string(), estimated_tokens)
+                .await;
+        }
+    }
+
+    /// Sends the request created by `build` for an endpoint, retrying it according to the
+    /// retry policy in effect for the call, then in the fallback locations if it still fails
+    /// with a retryable error. Responses with a non-success status are returned as
+    /// [`Error::HttpStatus`].
+    ///
+    /// Models the backend doesn't serve are rejected without sending anything. Endpoints and
+    /// models named by a full resource name don't fail over, as they belong to one location.
+    async fn send<F>(
+        &self,
+        model: &Model,
+        options: &RequestOptions,
+        build: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn(&Endpoint) -> reqwest::RequestBuilder,
+    {
+        self.inner.endpoint.check_model(model)?;
+        let build = |endpoint: &Endpoint| self.decorate(build(endpoint), options);
+        let failover = match model.name() {
+            ModelName::Endpoint(_) | ModelName::ResourceName(_) => None,
+            ModelName::Id(_) | ModelName::Publisher { .. } => self.inner.failover.as_ref(),
+        };
+        let Some(failover) = failover else {
+            return self
+                .send_with_retries(options, || build(&self.inner.endpoint))
+                .await;
+        };
+
+        let mut endpoints = failover
+            .endpoints(&self.inner.endpoint)
+            .into_iter()
+            .peekable();
+        loop {
+            let endpoint = endpoints
+                .next()
+                .expect("failover yields the primary endpoint");
+            let result = self.send_with_retries(options, || build(&endpoint)).await;
+            let failed = match &result {
+                Err(Error::HttpStatus(e)) => retry::is_retryable_status(e.status),
+                Err(e) => retry::is_retryable_error(e),
+                Ok(_) => false,
+            };
+            if !failed {
+                failover.mark_healthy(&endpoint);
+                return result;
+            }
+            failover.mark_unhealthy(&endpoint);
+            if endpoints.peek().is_none() {
+                return result;
+            }
+            tracing::warn!(
+                location = endpoint.location(),
+                "Location failed, failing over"
+            );
+        }
+    }
+
+    async fn send_with_retries<F>(
+        &self,
+        options: &RequestOptions,
+        build: F,
+    ) -> Result<reqwest::Response>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let policy = options
+            .retry_policy
+            .as_ref()
+            .unwrap_or(&self.inner.retry_policy);
+        let mut attempt = 1;
+        loop {
+            let can_retry = attempt < policy.max_attempts;
+            let result = match self.inner.service.call(build().build()?).await {
+                Ok(resp) if !resp.status().is_success() => {
+                    Err(HttpStatusError::from_response(resp).await.into())
+                }
+                result => result,
+            };
+            let delay = match result {
+                Err(Error::HttpStatus(e)) if can_retry && retry::is_retryable_status(e.status) => {
+                    let delay =
+                        retry::server_retry_delay(&e).unwrap_or_else(|| policy.backoff(attempt));
+                    tracing::warn!(attempt, status = %e.status, ?delay, "Request failed, retrying");
+                    delay
+                }
+                Err(e) if can_retry && retry::is_retryable_error(&e) => {
+                    let delay = policy.backoff(attempt);
+                    tracing::warn!(attempt, error = %e, ?delay, "Request failed, retrying");
+                    delay
+                }
+                result => {
+                    if attempt > 1 {
+                        tracing::info!(attempts = attempt, "Request completed after retries");
+                    }
+                    return result;
+                }
+            };
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// Reports the token usage of a call to the rate limiter and the usage tracker, once the final
+/// response arrives.
+struct UsageRecorder {
+    rate_limiter: Option<RateLimiter>,
+    usage_tracker: Option<UsageTracker>,
+    model: String,
+    tag: Option<String>,
+    estimated_tokens: u32,
+    finished: FinishedCandidates,
+}
+
+impl UsageRecorder {
+    fn record(&mut self, response: &GenerateContentResponseResult) {
+        let finished = self.finished.update(response);
+        let Some(usage_metadata) = response.usage_metadata.as_ref().filter(|_| finished) else {
+            return;
+        };
+        if let (Some(rate_limiter), Some(total_tokens)) =
+            (&self.rate_limiter, usage_metadata.total_token_count)
+        {
+            rate_limiter.record_usage(&self.model, self.estimated_tokens, total_tokens);
+        }
+        if let Some(usage_tracker) = &self.usage_tracker {
+            usage_tracker.record(&self.model, self.tag.as_deref(), usage_metadata);
+        }
+    }
+}
+
+pub struct GeminiClientBuilder<T: TokenProvider> {
+    token_provider: T,
+    http_client: Option<reqwest::Client>,
+    service: Option<HttpService>,
+    endpoint: Endpoint,
+    retry_policy: RetryPolicy,
+    rate_limiter: Option<RateLimiter>,
+    usage_tracker: Option<UsageTracker>,
+    timeouts: Timeouts,
+    failover: Option<Failover>,
+    headers: HeaderMap,
+    labels: HashMap<String, String>,
+    user_project: Option<String>,
+    user_agent_suffix: Option<String>,
+    scopes: Vec<String>,
+}
+
+impl<T: TokenProvider> GeminiClientBuilder<T> {
+    fn new(token_provider: T, backend: Backend) -> Self {
+        GeminiClientBuilder {
+            token_provider,
+            http_client: None,
+            service: None,
+            endpoint: Endpoint::new(backend),
+            retry_policy: RetryPolicy::none(),
+            rate_limiter: None,
+            usage_tracker: None,
+            timeouts: Timeouts::default(),
+            failover: None,
+            headers: HeaderMap::new(),
+            labels: HashMap::new(),
+            user_project: None,
+            user_agent_suffix: None,
+            scopes: AUTH_SCOPE.iter().map(|scope| scope.to_string()).collect(),
+        }
+    }
+
+    /// Sets the scheme, host and optional port requests are sent to, e.g.
+    /// `https://us-central1-aiplatform.googleapis.com` or `http://localhost:8080`. Defaults to
+    /// [`Backend::default_base_url`].
+    pub fn base_url<U: Into<String>>(mut self, base_url: U) -> Self {
+        self.endpoint.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Sets the API version used for every method, e.g. `v1` or `v1beta1`. By default each
+    /// method uses the version it is known to work with.
+    pub fn api_version<V: Into<String>>(mut self, api_version: V) -> Self {
+        self.endpoint.api_version = Some(api_version.into());
+        self
+    }
+
+    /// Sets the Vertex AI publisher serving the models. Defaults to `google`. Ignored for
+    /// [`Backend::AiStudio`].
+    pub fn publisher<P: Into<String>>(mut self, publisher: P) -> Self {
+        self.endpoint.publisher = publisher.into();
+        self
+    }
+
+    /// Sets the retry policy used by every call that doesn't override it through
+    /// [`RequestOptions`]. Defaults to [`RetryPolicy::none`].
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Throttles calls on the client to stay within the limiter's quotas. Token counts are only
+    /// tracked for content generation.
+    pub fn rate_limiter(mut self, rate_limiter: RateLimiter) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Records the token usage of content generation calls, see [`UsageTracker`].
+    pub fn usage_tracker(mut self, usage_tracker: UsageTracker) -> Self {
+        self.usage_tracker = Some(usage_tracker);
+        self
+    }
+
+    /// Sets the timeouts used by every call that doesn't override them through
+    /// [`RequestOptions`]. No timeouts are set by default.
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Retries requests in the given Vertex AI locations, in order, when they fail in the
+    /// client's location with a retryable error after exhausting the retry policy. `global`
+    /// can be used for the global endpoint. A location that fails is skipped for
+    /// [`GeminiClientBuilder::failover_cooldown`], unless all of them are failing. Ignored for
+    /// [`Backend::AiStudio`].
+    ///
+    /// A base URL set with [`GeminiClientBuilder::base_url`], or passed to
+    /// [`GeminiClient::new`], is used for every location. Endpoints and models named by a full
+    /// resource name are only sent to their own location.
+    pub fn fallback_locations<I, L>(mut self, locations: I) -> Self
+    where
+        I: IntoIterator<Item = L>,
+        L: Into<String>,
+    {
+        let locations = locations.into_iter().map(Into::into).collect();
+        let cooldown = self
+            .failover
+            .as_ref()
+            .map_or(DEFAULT_FAILOVER_COOLDOWN, |failover| failover.cooldown);
+        self.failover = Some(Failover::new(locations, cooldown));
+        self
+    }
+
+    /// Sets how long a failing location is skipped. Defaults to one minute.
+    pub fn failover_cooldown(mut self, cooldown: Duration) -> Self {
+        let locations = self
+            .failover
+            .take()
+            .map(|failover| failover.locations)
+            .unwrap_or_default();
+        self.failover = Some(Failover::new(locations, cooldown));
+        self
+    }
+
+    /// Sets the OAuth scopes passed to the token provider, e.g. to follow the principle of
+    /// least privilege. Defaults to [`AUTH_SCOPE`]. Can be overridden per call with
+    /// [`RequestOptions::scopes`]. Ignored by API key providers.
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.scopes = scopes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sends a header with every request. [`RequestOptions::headers`] replaces headers with the
+    /// same name for a call.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Adds a label to every content generation request, for cost attribution. Labels are
+    /// merged with the ones from [`RequestOptions::labels`] and the request itself, which take
+    /// precedence. Ignored for [`Backend::AiStudio`], which doesn't support labels.
+    pub fn label<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.labels.insert(key.into(), value.into());
+        self
+    }
+
+    /// Bills requests to the given project, sent in the `x-goog-user-project` header, instead
+    /// of the project of the credentials.
+    pub fn user_project<P: Into<String>>(mut self, user_project: P) -> Self {
+        self.user_project = Some(user_project.into());
+        self
+    }
+
+    /// Appends `suffix` to the `User-Agent` header sent with every request, which becomes
+    /// `gemini-rs/{version} {suffix}`. This replaces the user agent of an HTTP client passed to
+    /// [`GeminiClientBuilder::http_client`].
+    pub fn user_agent_suffix<S: Into<String>>(mut self, suffix: S) -> Self {
+        self.user_agent_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Sends requests with the given [`reqwest::Client`], e.g. one configured with proxies,
+    /// custom root certificates, pool sizes or HTTP/2 settings. By default a new client is
+    /// created.
+    ///
+    /// The crate relies on the `json`, `gzip` and `stream` features of `reqwest`, which are
+    /// enabled by its dependency on `reqwest`. Gzip compression can still be turned off on the
+    /// client with [`reqwest::ClientBuilder::no_gzip`]. The connect timeout from
+    /// [`GeminiClientBuilder::timeouts`] isn't applied to a client passed here.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Sends every request through the given [`tower::Service`] instead of the HTTP client,
+    /// so middleware such as timeouts, metrics or circuit breakers can be
+    /// composed around the client, e.g. with [`tower::ServiceBuilder`]:
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # use gemini_rs::prelude::*;
+    /// # fn client(token_provider: ApiKey) -> GeminiClient<ApiKey> {
+    /// let service = tower::ServiceBuilder::new()
+    ///     .timeout(Duration::from_secs(30))
+    ///     .service(reqwest::Client::new());
+    /// GeminiClient::builder(token_provider, Backend::AiStudio)
+    ///     .service(service)
+    ///     .build()
+    /// # }
+    /// ```
+    ///
+    /// Errors raised by the middleware are returned as [`Error::Middleware`]. Retries configured
+    /// with [`GeminiClientBuilder::retry_policy`] happen outside of the service stack.
+    pub fn service<S>(mut self, service: S) -> Self
+    where
+        S: Service<reqwest::Request, Response = reqwest::Response> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        self.service = Some(HttpService::new(service));
+        self
+    }
+
+    pub fn build(self) -> GeminiClient<T> {
+        let client = self.http_client.unwrap_or_else(|| {
+            let mut client = reqwest::Client::builder();
+            if let Some(connect_timeout) = self.timeouts.connect {
+                client = client.connect_timeout(connect_timeout);
+            }
+            client.build().expect("failed to build the HTTP client")
+        });
+        let inner = ClientInner {
+            token_provider: self.token_provider,
+            service: self
+                .service
+                .unwrap_or_else(|| HttpService::new(client.clone())),
+            client,
+            endpoint: self.endpoint,
+            retry_policy: self.retry_policy,
+            rate_limiter: self.rate_limiter,
+            usage_tracker: self.usage_tracker,
+            timeouts: self.timeouts,
+            failover: self.failover,
+            headers: self.headers,
+            labels: self.labels,
+            user_project: self.user_project,
+            user_agent_suffix: self.user_agent_suffix,
+            scopes: self.scopes,
+        };
+        GeminiClient {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use reqwest::header::{HeaderName, HeaderValue};
+    use tokio_stream::StreamExt;
+
+    use super::GeminiClient;
+    use crate::backend::Backend;
+    use crate::error::Error;
+    use crate::prelude::{
+        ApiKey, Content, CountTokensRequestBuilder, FnTokenProvider, GenerateContentRequest,
+        GenerationConfig, Model, RequestOptions, RetryPolicy, TextEmbeddingRequest,
+        TextEmbeddingRequestInstance, TimeoutKind, Timeouts, TokenProvider, UsageTracker,
+    };
+    use crate::test_support::{FakeToken, MockResponse, MockServer};
+
+    const RESPONSE: &str =
+        r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hello"}]}}]}"#;
+
+    fn vertex() -> Backend {
+        Backend::VertexAi {
+            project_id: "project".to_string(),
+            location_id: "us-central1".to_string(),
+        }
+    }
+
+    fn request() -> GenerateContentRequest {
+        GenerateContentRequest::builder()
+            .contents(vec![Content::builder().add_text_part("Hi").build()])
+            .build()
+    }
+
+    #[tokio::test]
+    async fn sends_requests_to_base_url() {
+        let server = MockServer::start(vec![MockResponse::json(200, RESPONSE)]).await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(format!("{}/", server.url()))
+            .build();
+
+        let response = client.generate_content(&request(), "gemini").await.unwrap();
+        assert_eq!(response.candidates[0].get_text().unwrap(), "Hello");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "POST");
+        assert_eq!(
+            requests[0].path,
+            "/v1beta1/projects/project/locations/us-central1/publishers/google/models/gemini:generateContent"
+        );
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Bearer fake-token")
         );
+    }
 
-        let access_token = self.token_provider.get_token(AUTH_SCOPE).await?;
-        let resp = self
-            .client
-            .post(&endpoint_url)
-            .bearer_auth(access_token)
-            .json(&request)
-            .send()
-            .await?;
+    #[tokio::test]
+    async fn overrides_api_version_and_publisher() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            r#"{"totalTokens": 3, "totalBillableCharacters": 10}"#,
+        )])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .api_version("v1")
+            .publisher("acme")
+            .build();
 
-        let txt_json = resp.text().await?;
+        let request = CountTokensRequestBuilder::from_prompt("Hi").build();
+        client.count_tokens(&request, "model").await.unwrap();
+
+        assert_eq!(
+            server.requests()[0].path,
+            "/v1/projects/project/locations/us-central1/publishers/acme/models/model:countTokens"
+        );
+    }
+
+    #[tokio::test]
+    async fn uses_ai_studio_urls_and_api_key() {
+        let server =
+            MockServer::start(vec![MockResponse::json(200, r#"{"totalTokens": 3}"#)]).await;
+        let client = GeminiClient::builder(ApiKey::new("key"), Backend::AiStudio)
+            .base_url(server.url())
+            .build();
+
+        let request = CountTokensRequestBuilder::from_prompt("Hi").build();
+        client.count_tokens(&request, "gemini").await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests[0].path, "/v1beta/models/gemini:countTokens");
+        assert_eq!(requests[0].header("x-goog-api-key"), Some("key"));
+        assert!(requests[0].body.starts_with(r#"{"contents":[{"#));
+    }
+
+    #[tokio::test]
+    async fn calls_ai_studio_batch_embed_contents() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            r#"{"embeddings": [{"values": [0.1, 0.2]}, {"values": [0.3]}]}"#,
+        )])
+        .await;
+        let client = GeminiClient::builder(ApiKey::new("key"), Backend::AiStudio)
+            .base_url(server.url())
+            .build();
+
+        let instance = |content: &str, title: Option<&str>| TextEmbeddingRequestInstance {
+            content: content.to_string(),
+            task_type: "RETRIEVAL_DOCUMENT".to_string(),
+            title: title.map(str::to_string),
+        };
+        let request = TextEmbeddingRequest {
+            instances: vec![instance("Hello", Some("Greeting")), instance("World", None)],
+        };
+        let response = client
+            .text_embeddings(&request, Model::TEXT_EMBEDDING_004)
+            .await
+            .unwrap()
+            .into_result()
+            .unwrap();
+
+        let values: Vec<_> = response
+            .predictions
+            .iter()
+            .map(|prediction| prediction.embeddings.values.clone())
+            .collect();
+        assert_eq!(values, [vec![0.1, 0.2], vec![0.3]]);
+        assert_eq!(response.predictions[0].embeddings.statistics.token_count, 0);
+
+        let request = &server.requests()[0];
+        assert_eq!(
+            request.path,
+            "/v1beta/models/text-embedding-004:batchEmbedContents"
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"requests": [
+                {
+                    "model": "models/text-embedding-004",
+                    "content": {"role": null, "parts": [{"text": "Hello"}]},
+                    "taskType": "RETRIEVAL_DOCUMENT",
+                    "title": "Greeting",
+                },
+                {
+                    "model": "models/text-embedding-004",
+                    "content": {"role": null, "parts": [{"text": "World"}]},
+                    "taskType": "RETRIEVAL_DOCUMENT",
+                },
+            ]})
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_with_server_provided_delays() {
+        let server = MockServer::start(vec![
+            MockResponse::json(503, "Service Unavailable").header("Retry-After", "0"),
+            MockResponse::json(
+                429,
+                r#"{"error": {"code": 429, "message": "Quota exceeded", "status": "RESOURCE_EXHAUSTED", "details": [
+                    {"@type": "type.googleapis.com/google.rpc.QuotaFailure", "violations": []},
+                    {"@type": "type.googleapis.com/google.rpc.RetryInfo", "retryDelay": "0s"}
+                ]}}"#,
+            ),
+            MockResponse::json(200, RESPONSE),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+        let options = RequestOptions::builder()
+            .retry_policy(RetryPolicy::default())
+            .build();
+
+        let response = client
+            .generate_content_with_options(&request(), "gemini", &options)
+            .await
+            .unwrap();
+        assert_eq!(response.candidates[0].get_text().unwrap(), "Hello");
+        assert_eq!(server.requests().len(), 3);
+    }
 
-        match serde_json::from_str::<PredictImageResponse>(&txt_json) {
-            Ok(response) => Ok(response),
-            Err(e) => {
-                error!(response = txt_json, error = ?e, "Failed to parse response");
-                Err(e.into())
+    #[tokio::test]
+    async fn maps_error_statuses_to_http_status_errors() {
+        let server = MockServer::start(vec![
+            MockResponse::json(502, "<html>Bad Gateway</html>"),
+            MockResponse::json(
+                400,
+                r#"{"error": {"code": 400, "message": "Invalid model", "status": "INVALID_ARGUMENT"}}"#,
+            ),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        let Err(Error::HttpStatus(e)) = client.generate_content(&request(), "gemini").await else {
+            panic!("expected an HTTP status error");
+        };
+        assert_eq!(e.status, 502);
+        assert_eq!(e.body, "<html>Bad Gateway</html>");
+        assert!(e.vertex_error.is_none());
+
+        let Err(Error::HttpStatus(e)) = client.generate_content(&request(), "gemini").await else {
+            panic!("expected an HTTP status error");
+        };
+        assert_eq!(e.status, 400);
+        assert_eq!(e.vertex_error.unwrap().status, "INVALID_ARGUMENT");
+    }
+
+    #[tokio::test]
+    async fn sends_requests_through_the_service_stack() {
+        let service = tower::service_fn(|_: reqwest::Request| async {
+            Err::<reqwest::Response, tower::BoxError>("circuit open".into())
+        });
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .service(service)
+            .build();
+
+        let result = client.generate_content(&request(), "gemini").await;
+        assert!(matches!(result, Err(Error::Middleware(e)) if e.to_string() == "circuit open"));
+    }
+
+    #[tokio::test]
+    async fn fails_calls_exceeding_the_total_timeout() {
+        let server = MockServer::start(vec![
+            MockResponse::json(200, RESPONSE).delay(Duration::from_secs(5))
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .timeouts(Timeouts {
+                total: Some(Duration::from_secs(60)),
+                ..Default::default()
+            })
+            .build();
+        let options = RequestOptions::builder()
+            .timeouts(Timeouts {
+                total: Some(Duration::from_millis(100)),
+                ..Default::default()
+            })
+            .build();
+
+        let result = client
+            .generate_content_with_options(&request(), "gemini", &options)
+            .await;
+        assert!(matches!(result, Err(Error::Timeout(TimeoutKind::Total))));
+    }
+
+    #[tokio::test]
+    async fn sends_requests_with_the_injected_http_client() {
+        let server = MockServer::start(vec![MockResponse::json(200, RESPONSE)]).await;
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("x-proxy-tag", "corp".parse().unwrap());
+        let http_client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .http_client(http_client)
+            .build();
+
+        client.generate_content(&request(), "gemini").await.unwrap();
+        assert_eq!(server.requests()[0].header("x-proxy-tag"), Some("corp"));
+    }
+
+    #[tokio::test]
+    async fn fails_over_to_fallback_locations() {
+        let server = MockServer::start(vec![
+            MockResponse::json(
+                503,
+                r#"{"error": {"code": 503, "message": "Overloaded", "status": "UNAVAILABLE"}}"#,
+            ),
+            MockResponse::json(200, RESPONSE),
+            MockResponse::json(200, RESPONSE),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .fallback_locations(["europe-west4", "global"])
+            .build();
+
+        client.generate_content(&request(), "gemini").await.unwrap();
+        client.generate_content(&request(), "gemini").await.unwrap();
+
+        let locations: Vec<_> = server
+            .requests()
+            .iter()
+            .map(|request| request.path.split('/').nth(5).unwrap().to_string())
+            .collect();
+        // The failed location is skipped during its cool-down.
+        assert_eq!(locations, ["us-central1", "europe-west4", "europe-west4"]);
+    }
+
+    #[tokio::test]
+    async fn does_not_fail_over_resource_names() {
+        let server = MockServer::start(vec![
+            MockResponse::json(
+                503,
+                r#"{"error": {"code": 503, "message": "Overloaded", "status": "UNAVAILABLE"}}"#,
+            ),
+            MockResponse::json(
+                503,
+                r#"{"error": {"code": 503, "message": "Overloaded", "status": "UNAVAILABLE"}}"#,
+            ),
+            MockResponse::json(200, RESPONSE),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .fallback_locations(["europe-west4"])
+            .build();
+
+        let endpoint = "projects/project/locations/us-central1/endpoints/1234";
+        let result = client.generate_content(&request(), endpoint).await;
+        assert!(matches!(result, Err(Error::HttpStatus(e)) if e.status == 503));
+        let result = client
+            .generate_content(&request(), Model::endpoint("1234"))
+            .await;
+        assert!(matches!(result, Err(Error::HttpStatus(e)) if e.status == 503));
+        // The primary location wasn't marked unhealthy.
+        client.generate_content(&request(), "gemini").await.unwrap();
+
+        let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+        assert_eq!(
+            paths,
+            [
+                format!("/v1beta1/{endpoint}:generateContent"),
+                format!("/v1beta1/{endpoint}:generateContent"),
+                "/v1beta1/projects/project/locations/us-central1/publishers/google/models/gemini:generateContent".to_string(),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_resource_names_and_rejects_unsupported_requests() {
+        let server = MockServer::start(vec![MockResponse::json(200, RESPONSE)]).await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        let endpoint = "projects/project/locations/us-central1/endpoints/1234";
+        client.generate_content(&request(), endpoint).await.unwrap();
+        assert_eq!(
+            server.requests()[0].path,
+            format!("/v1beta1/{endpoint}:generateContent")
+        );
+
+        let request = GenerateContentRequest::builder()
+            .contents(request().contents)
+            .generation_config(
+                GenerationConfig::builder()
+                    .max_output_tokens(100_000)
+                    .build(),
+            )
+            .build();
+        let result = client
+            .generate_content(&request, Model::GEMINI_2_0_FLASH_001)
+            .await;
+        assert!(matches!(result, Err(Error::UnsupportedByModel(_))));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn calls_endpoints_and_publisher_models() {
+        let server = MockServer::start(vec![
+            MockResponse::json(200, RESPONSE),
+            MockResponse::json(200, r#"{"totalTokens": 3}"#),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        client
+            .generate_content(&request(), Model::endpoint("1234"))
+            .await
+            .unwrap();
+        let request = CountTokensRequestBuilder::from_prompt("Hi").build();
+        client
+            .count_tokens(&request, "publishers/meta/models/llama")
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].path,
+            "/v1beta1/projects/project/locations/us-central1/endpoints/1234:generateContent"
+        );
+        assert_eq!(
+            requests[1].path,
+            "/v1beta1/projects/project/locations/us-central1/publishers/meta/models/llama:countTokens"
+        );
+    }
+
+    #[test]
+    fn is_shareable_without_a_cloneable_token_provider() {
+        struct SharedToken;
+        impl TokenProvider for SharedToken {
+            async fn get_token(&self, _scope: &[&str]) -> crate::error::Result<String> {
+                Ok("token".to_string())
             }
         }
+        fn assert_shareable<C: Clone + Send + Sync + 'static>(_: &C) {}
+
+        let client = GeminiClient::with_backend(SharedToken, Backend::AiStudio);
+        assert_shareable(&client);
+    }
+
+    #[tokio::test]
+    async fn sends_headers_and_labels() {
+        let server = MockServer::start(vec![MockResponse::json(200, RESPONSE)]).await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .header(
+                HeaderName::from_static("x-goog-request-params"),
+                HeaderValue::from_static("client"),
+            )
+            .user_project("billing")
+            .user_agent_suffix("my-app/1.0")
+            .label("team", "search")
+            .label("env", "prod")
+            .build();
+        let options = RequestOptions::builder()
+            .header(
+                HeaderName::from_static("x-goog-request-params"),
+                HeaderValue::from_static("call"),
+            )
+            .label("env", "dev")
+            .build();
+
+        client
+            .generate_content_with_options(&request(), "gemini", &options)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("x-goog-request-params"), Some("call"));
+        assert_eq!(request.header("x-goog-user-project"), Some("billing"));
+        assert_eq!(
+            request.header("user-agent"),
+            Some(format!("gemini-rs/{} my-app/1.0", env!("CARGO_PKG_VERSION")).as_str())
+        );
+        let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+        assert_eq!(
+            body["labels"],
+            serde_json::json!({"team": "search", "env": "dev"})
+        );
+    }
+
+    #[tokio::test]
+    async fn uses_express_mode_urls_and_api_key() {
+        let server = MockServer::start(vec![MockResponse::json(200, RESPONSE)]).await;
+        let token_provider =
+            FnTokenProvider::new(|_scope: &[&str]| async { Ok("express-key".to_string()) });
+        let client = GeminiClient::builder(token_provider, Backend::VertexAiExpress)
+            .base_url(server.url())
+            .build();
+
+        client.generate_content(&request(), "gemini").await.unwrap();
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].path,
+            "/v1beta1/publishers/google/models/gemini:generateContent"
+        );
+        assert_eq!(requests[0].header("x-goog-api-key"), Some("express-key"));
+        assert_eq!(requests[0].header("authorization"), None);
+
+        // Publisher models and endpoints are rejected without sending a request.
+        let result = client
+            .generate_content(&request(), Model::publisher_model("meta", "llama"))
+            .await;
+        assert!(matches!(result, Err(Error::UnsupportedByModel(_))));
+        let client = GeminiClient::builder(ApiKey::new("key"), Backend::AiStudio)
+            .base_url(server.url())
+            .build();
+        let result = client
+            .generate_content(&request(), Model::endpoint("1234"))
+            .await;
+        assert!(matches!(result, Err(Error::UnsupportedByModel(_))));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn requests_tokens_for_the_configured_scopes() {
+        let server = MockServer::start(vec![
+            MockResponse::json(200, RESPONSE),
+            MockResponse::json(200, RESPONSE),
+        ])
+        .await;
+        let scopes = Arc::new(Mutex::new(vec![]));
+        let recorded = scopes.clone();
+        let token_provider = FnTokenProvider::new(move |scope: &[&str]| {
+            recorded.lock().unwrap().push(scope.join(" "));
+            async { Ok("token".to_string()) }
+        });
+        let client = GeminiClient::builder(token_provider, vertex())
+            .base_url(server.url())
+            .scopes(["client-scope"])
+            .build();
+        let options = RequestOptions::builder()
+            .scopes(["call-scope", "other-scope"])
+            .build();
+
+        client.generate_content(&request(), "gemini").await.unwrap();
+        client
+            .generate_content_with_options(&request(), "gemini", &options)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *scopes.lock().unwrap(),
+            ["client-scope", "call-scope other-scope"]
+        );
+    }
+
+    #[tokio::test]
+    async fn streams_server_sent_events() {
+        let server = MockServer::start(vec![MockResponse::sse(&[
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hel"}]}}]}"#,
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "lo"}]}, "finishReason": "STOP"}]}"#,
+        ])])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        let stream = client
+            .generate_content_stream(&request(), "gemini")
+            .await
+            .unwrap();
+        let texts: Vec<_> = stream
+            .map(|response| response.unwrap().candidates[0].get_text().unwrap())
+            .collect()
+            .await;
+        assert_eq!(texts, ["Hel", "lo"]);
+        assert_eq!(
+            server.requests()[0].path,
+            "/v1beta1/projects/project/locations/us-central1/publishers/google/models/gemini:streamGenerateContent?alt=sse"
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_stream_setup_failures_as_errors() {
+        let token_provider = FnTokenProvider::new(|_scope: &[&str]| async {
+            Err(Error::Credentials("expired".to_string()))
+        });
+        let client = GeminiClient::builder(token_provider, vertex()).build();
+        let result = client.generate_content_stream(&request(), "gemini").await;
+        assert!(matches!(result, Err(Error::Credentials(_))));
+
+        let server = MockServer::start(vec![MockResponse::json(
+            403,
+            r#"{"error": {"code": 403, "message": "Permission denied", "status": "PERMISSION_DENIED"}}"#,
+        )])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+        let result = client.generate_content_stream(&request(), "gemini").await;
+        assert!(matches!(result, Err(Error::HttpStatus(e)) if e.status == 403));
+    }
+
+    #[tokio::test]
+    async fn resumes_dropped_streams_from_the_received_text() {
+        let server = MockServer::start(vec![
+            MockResponse::sse(&[
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Once upon"}]}}]}"#,
+            ]),
+            MockResponse::sse(&[
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": " a time"}]}, "finishReason": "STOP"}]}"#,
+            ]),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        let stream = client
+            .generate_content_stream_resumable(&request(), "gemini", &RequestOptions::default(), 1)
+            .await
+            .unwrap();
+        let texts: Vec<_> = stream
+            .map(|response| response.unwrap().candidates[0].get_text().unwrap())
+            .collect()
+            .await;
+        assert_eq!(texts, ["Once upon", " a time"]);
+
+        let body: serde_json::Value = serde_json::from_str(&server.requests()[1].body).unwrap();
+        assert_eq!(
+            body["contents"][1],
+            serde_json::json!({"role": "model", "parts": [{"text": "Once upon"}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn tracks_the_usage_of_streamed_and_unary_calls() {
+        let finished = concat!(
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hi"}]}, "finishReason": "STOP"}], "#,
+            r#""usageMetadata": {"promptTokenCount": 10, "candidatesTokenCount": 2, "thoughtsTokenCount": 5, "totalTokenCount": 17}}"#,
+        );
+        let server = MockServer::start(vec![
+            MockResponse::json(200, finished),
+            MockResponse::sse(&[
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "H"}]}}], "usageMetadata": {"promptTokenCount": 10, "totalTokenCount": 10}}"#,
+                finished,
+            ]),
+        ])
+        .await;
+        let tracker = UsageTracker::default();
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .usage_tracker(tracker.clone())
+            .build();
+
+        let options = RequestOptions::builder().usage_tag("chat").build();
+        client
+            .generate_content_with_options(&request(), "gemini", &options)
+            .await
+            .unwrap();
+        client
+            .generate_content_stream(&request(), "gemini")
+            .await
+            .unwrap()
+            .collect_response()
+            .await
+            .unwrap();
+
+        let total = tracker.total();
+        assert_eq!(total.calls, 2);
+        assert_eq!(total.total_tokens, 34);
+        assert_eq!(total.thoughts_tokens, 10);
+        assert_eq!(tracker.by_model()["gemini"].calls, 2);
+        assert_eq!(tracker.by_tag()["chat"].calls, 1);
+    }
+
+    #[tokio::test]
+    async fn keeps_the_total_timeout_across_resumes() {
+        let chunk =
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Once"}]}}]}"#;
+        let server = MockServer::start(vec![
+            MockResponse::sse(&[chunk]).delay(Duration::from_millis(200)),
+            MockResponse::sse(&[chunk]).delay(Duration::from_millis(200)),
+        ])
+        .await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .timeouts(Timeouts {
+                total: Some(Duration::from_millis(300)),
+                ..Default::default()
+            })
+            .build();
+
+        let stream = client
+            .generate_content_stream_resumable(&request(), "gemini", &RequestOptions::default(), 3)
+            .await
+            .unwrap();
+        let items: Vec<_> = stream.collect().await;
+        assert!(matches!(
+            items[..],
+            [Ok(_), Err(Error::Timeout(TimeoutKind::Total))]
+        ));
+    }
+
+    #[tokio::test]
+    async fn yields_an_error_for_malformed_events() {
+        let server = MockServer::start(vec![MockResponse::sse(&["not json"])]).await;
+        let client = GeminiClient::builder(FakeToken, vertex())
+            .base_url(server.url())
+            .build();
+
+        let mut stream = client
+            .generate_content_stream(&request(), "gemini")
+            .await
+            .unwrap();
+        assert!(matches!(stream.next().await, Some(Err(Error::Serde(_)))));
     }
 }
@@ -31,7 +31,7 @@ impl Dialogue {
         }
     }
 
-    pub async fn do_turn<T: TokenProvider + Clone>(
+    pub async fn do_turn<T: TokenProvider>(
         &mut self,
         gemini: &GeminiClient<T>,
         message: &str,
@@ -1,8 +1,10 @@
 use std::fmt::Display;
 
+use reqwest::{header::HeaderMap, Response, StatusCode};
 use reqwest_eventsource::CannotCloneRequestError;
 
-use crate::types;
+use crate::timeout::TimeoutKind;
+use crate::types::{self, GenerateContentResponseError};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -15,8 +17,63 @@ pub enum Error {
     VertexError(types::VertexApiError),
     NoCandidatesError,
     CannotCloneRequestError(CannotCloneRequestError),
-    EventSourceError(reqwest_eventsource::Error),
+    /// Boxed, as it holds the whole failed response.
+    EventSourceError(Box<reqwest_eventsource::Error>),
     EventSourceClosedError,
+    HttpStatus(Box<HttpStatusError>),
+    /// An error raised by a middleware in the service stack set with
+    /// [`crate::prelude::GeminiClientBuilder::service`].
+    Middleware(tower::BoxError),
+    /// One of the [`crate::prelude::Timeouts`] configured for the call expired.
+    Timeout(TimeoutKind),
+    /// The request uses a feature the model doesn't support, see
+    /// [`crate::prelude::Model::validate`].
+    UnsupportedByModel(String),
+    /// Credentials couldn't be loaded or used, e.g. because of an invalid configuration.
+    Credentials(String),
+}
+
+/// A response with a non-success HTTP status code.
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    /// The raw response body, which may not be JSON, e.g. for errors from a load balancer.
+    pub body: String,
+    /// The error payload, when the body contains one in the Google API error format.
+    pub vertex_error: Option<types::VertexApiError>,
+}
+
+impl HttpStatusError {
+    /// Consumes the response, reading its body.
+    pub(crate) async fn from_response(response: Response) -> Self {
+        let status = response.status();
+        let headers = response.headers().clone();
+        let body = response.text().await.unwrap_or_default();
+        // Errors are wrapped in a list by the streaming methods when SSE isn't requested.
+        let vertex_error = match serde_json::from_str::<GenerateContentResponseError>(&body) {
+            Ok(error) => Some(error.error),
+            Err(_) => serde_json::from_str::<Vec<GenerateContentResponseError>>(&body)
+                .ok()
+                .and_then(|mut errors| errors.pop())
+                .map(|error| error.error),
+        };
+        HttpStatusError {
+            status,
+            headers,
+            body,
+            vertex_error,
+        }
+    }
+}
+
+impl Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.vertex_error {
+            Some(e) => write!(f, "HTTP {}: {}", self.status, e.message),
+            None => write!(f, "HTTP {}: {}", self.status, self.body),
+        }
+    }
 }
 
 impl Display for Error {
@@ -41,6 +98,11 @@ impl Display for Error {
             Error::EventSourceClosedError => {
                 write!(f, "EventSource closed error")
             }
+            Error::HttpStatus(e) => write!(f, "HTTP status error: {}", e),
+            Error::Middleware(e) => write!(f, "Middleware error: {}", e),
+            Error::Timeout(kind) => write!(f, "Timeout error: {} timeout expired", kind),
+            Error::UnsupportedByModel(e) => write!(f, "Unsupported by model: {}", e),
+            Error::Credentials(e) => write!(f, "Credentials error: {}", e),
         }
     }
 }
@@ -85,6 +147,12 @@ impl From<CannotCloneRequestError> for Error {
 
 impl From<reqwest_eventsource::Error> for Error {
     fn from(e: reqwest_eventsource::Error) -> Self {
-        Error::EventSourceError(e)
+        Error::EventSourceError(Box::new(e))
+    }
+}
+
+impl From<HttpStatusError> for Error {
+    fn from(e: HttpStatusError) -> Self {
+        Error::HttpStatus(Box::new(e))
     }
 }
@@ -0,0 +1,195 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio_stream::Stream;
+
+use crate::error::Result;
+use crate::stream::{candidate_index, ResponseStream};
+use crate::types::{Citation, GenerateContentResponseResult, Part, SafetyRating, UsageMetadata};
+
+/// The finish reasons of responses blocked by safety filters.
+const SAFETY_FINISH_REASONS: &[&str] = &["SAFETY", "BLOCKLIST", "PROHIBITED_CONTENT", "SPII"];
+
+/// What happened in a streamed response, as yielded by [`StreamEvents`].
+#[derive(Clone, Debug)]
+pub enum StreamEvent {
+    /// Text generated since the previous event.
+    TextDelta(String),
+    FunctionCall {
+        name: String,
+        args: HashMap<String, String>,
+    },
+    Citation(Citation),
+    /// The response was stopped by a safety filter. Followed by [`StreamEvent::Finished`].
+    SafetyBlocked {
+        reason: String,
+        /// The ratings which blocked the response, or all the ratings if none is flagged.
+        ratings: Vec<SafetyRating>,
+    },
+    /// The last event of the response.
+    Finished {
+        reason: String,
+        usage: Option<UsageMetadata>,
+    },
+}
+
+/// The events of one candidate of a [`ResponseStream`], as returned by
+/// [`ResponseStream::events`].
+///
+/// ```no_run
+/// # use gemini_rs::prelude::*;
+/// # use tokio_stream::StreamExt;
+/// # async fn run(client: GeminiClient<ApiKey>, request: GenerateContentRequest) -> Result<(), gemini_rs::error::Error> {
+/// let mut events = client
+///     .generate_content_stream(&request, Model::GEMINI_2_0_FLASH_001)
+///     .await?
+///     .events();
+/// while let Some(event) = events.next().await {
+///     if let StreamEvent::TextDelta(text) = event? {
+///         print!("{}", text);
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct StreamEvents {
+    inner: ResponseStream,
+    candidate: usize,
+    pending: VecDeque<StreamEvent>,
+}
+
+impl StreamEvents {
+    pub(crate) fn new(inner: ResponseStream, candidate: usize) -> Self {
+        StreamEvents {
+            inner,
+            candidate,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn push_events(&mut self, response: GenerateContentResponseResult) {
+        let candidate = response
+            .candidates
+            .into_iter()
+            .enumerate()
+            .find(|(position, candidate)| candidate_index(*position, candidate) == self.candidate)
+            .map(|(_, candidate)| candidate);
+        let Some(candidate) = candidate else {
+            return;
+        };
+
+        let parts = candidate.content.and_then(|content| content.parts);
+        for part in parts.into_iter().flatten() {
+            match part {
+                Part::Text(text) if !text.is_empty() => {
+                    self.pending.push_back(StreamEvent::TextDelta(text))
+                }
+                Part::FunctionCall { name, args } => self
+                    .pending
+                    .push_back(StreamEvent::FunctionCall { name, args }),
+                _ => {}
+            }
+        }
+        if let Some(citation_metadata) = candidate.citation_metadata {
+            self.pending.extend(
+                citation_metadata
+                    .citations
+                    .into_iter()
+                    .map(StreamEvent::Citation),
+            );
+        }
+        let Some(reason) = candidate.finish_reason else {
+            return;
+        };
+        if SAFETY_FINISH_REASONS.contains(&reason.as_str()) {
+            let ratings = candidate.safety_ratings.unwrap_or_default();
+            let blocked: Vec<_> = ratings
+                .iter()
+                .filter(|rating| rating.blocked == Some(true))
+                .cloned()
+                .collect();
+            self.pending.push_back(StreamEvent::SafetyBlocked {
+                reason: reason.clone(),
+                ratings: if blocked.is_empty() { ratings } else { blocked },
+            });
+        }
+        self.pending.push_back(StreamEvent::Finished {
+            reason,
+            usage: response.usage_metadata,
+        });
+    }
+}
+
+impl Stream for StreamEvents {
+    type Item = Result<StreamEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            match ready!(Pin::new(&mut self.inner).poll_next(cx)) {
+                Some(Ok(response)) => self.push_events(response),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+    use tokio_stream::StreamExt;
+
+    use super::StreamEvent;
+    use crate::stream::ResponseStream;
+    use crate::timeout::{TimeoutStream, Timeouts};
+    use crate::types::GenerateContentResponse;
+
+    #[tokio::test]
+    async fn yields_typed_events_of_the_candidate() {
+        let responses = [
+            r#"{"candidates": [{"content": {"role": "model", "parts": [{"text": "Hel"}]}}]}"#,
+            r#"{"candidates": [{"content": {"role": "model", "parts": [
+                {"text": "lo"},
+                {"functionCall": {"name": "lookup", "args": {"city": "Paris"}}}
+            ]}, "citationMetadata": {"citations": [{"uri": "https://example.com"}]}}]}"#,
+            r#"{"candidates": [{"finishReason": "SAFETY", "safetyRatings": [
+                {"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"},
+                {"category": "HARM_CATEGORY_HATE_SPEECH", "probability": "HIGH", "blocked": true}
+            ]}], "usageMetadata": {"totalTokenCount": 9}}"#,
+        ]
+        .map(|json| {
+            serde_json::from_str::<GenerateContentResponse>(json)
+                .unwrap()
+                .into_result()
+        });
+        let inner = Box::pin(tokio_stream::iter(responses));
+        let stream = ResponseStream::new(TimeoutStream::new(
+            inner,
+            Instant::now(),
+            &Timeouts::default(),
+        ));
+
+        let events: Vec<_> = stream.events().map(Result::unwrap).collect().await;
+        assert!(matches!(
+            &events[..],
+            [
+                StreamEvent::TextDelta(hel),
+                StreamEvent::TextDelta(lo),
+                StreamEvent::FunctionCall { name, .. },
+                StreamEvent::Citation(_),
+                StreamEvent::SafetyBlocked { ratings, .. },
+                StreamEvent::Finished { reason, usage: Some(usage) },
+            ] if hel == "Hel"
+                && lo == "lo"
+                && name == "lookup"
+                && ratings.len() == 1
+                && ratings[0].category == "HARM_CATEGORY_HATE_SPEECH"
+                && reason == "SAFETY"
+                && usage.total_token_count == Some(9)
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::Endpoint;
+
+/// Tracks the health of the Vertex AI locations a client can fail over to.
+///
+/// A location whose requests fail with a retryable error is skipped for a cool-down period,
+/// unless every location is in cool-down.
+#[derive(Clone, Debug)]
+pub(crate) struct Failover {
+    pub locations: Vec<String>,
+    pub cooldown: Duration,
+    unhealthy_until: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl Failover {
+    pub fn new(locations: Vec<String>, cooldown: Duration) -> Self {
+        Failover {
+            locations,
+            cooldown,
+            unhealthy_until: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// The endpoints to try in order: the primary one followed by the fallback locations,
+    /// leaving out the ones in cool-down.
+    pub fn endpoints(&self, primary: &Endpoint) -> Vec<Endpoint> {
+        let mut endpoints = vec![primary.clone()];
+        endpoints.extend(
+            self.locations
+                .iter()
+                .filter(|location| Some(location.as_str()) != primary.location())
+                .filter_map(|location| primary.with_location(location)),
+        );
+
+        let now = Instant::now();
+        let unhealthy_until = self.unhealthy_until.lock().unwrap();
+        let is_healthy = |endpoint: &Endpoint| {
+            endpoint
+                .location()
+                .and_then(|location| unhealthy_until.get(location))
+                .is_none_or(|until| *until <= now)
+        };
+        if endpoints.iter().any(is_healthy) {
+            endpoints.retain(is_healthy);
+        }
+        endpoints
+    }
+
+    pub fn mark_unhealthy(&self, endpoint: &Endpoint) {
+        if let Some(location) = endpoint.location() {
+            let until = Instant::now() + self.cooldown;
+            let mut unhealthy_until = self.unhealthy_until.lock().unwrap();
+            unhealthy_until.insert(location.to_string(), until);
+        }
+    }
+
+    pub fn mark_healthy(&self, endpoint: &Endpoint) {
+        if let Some(location) = endpoint.location() {
+            self.unhealthy_until.lock().unwrap().remove(location);
+        }
+    }
+}
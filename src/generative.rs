@@ -0,0 +1,183 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use tokio_stream::Stream;
+
+use crate::client::GeminiClient;
+use crate::error::Result;
+use crate::model::Model;
+use crate::request_options::RequestOptions;
+use crate::token_provider::TokenProvider;
+use crate::types::{
+    CountTokensRequest, CountTokensResponse, GenerateContentRequest, GenerateContentResponseResult,
+    TextEmbeddingRequest, TextEmbeddingResponse,
+};
+
+/// A boxed future returned by [`GenerativeClient`] methods.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'a>>;
+
+/// A boxed stream of results, as returned by [`GenerativeClient::generate_content_stream`].
+pub type BoxStream<'a, T> = Pin<Box<dyn Stream<Item = Result<T>> + Send + 'a>>;
+
+/// The calls of a [`GeminiClient`], as an object-safe trait.
+///
+/// Code depending on `Box<dyn GenerativeClient>` or `Arc<dyn GenerativeClient>` rather than on
+/// a `GeminiClient<T>` doesn't need to be generic over the token provider, and can be given a
+/// fake in tests:
+///
+/// ```
+/// # use gemini_rs::prelude::*;
+/// struct Fake;
+///
+/// impl GenerativeClient for Fake {
+///     fn generate_content<'a>(
+///         &'a self,
+///         _request: &'a GenerateContentRequest,
+///         _model: &'a Model,
+///         _options: &'a RequestOptions,
+///     ) -> BoxFuture<'a, GenerateContentResponseResult> {
+///         Box::pin(async { Err(gemini_rs::error::Error::NoCandidatesError) })
+///     }
+///
+///     // ...
+/// #   fn count_tokens<'a>(
+/// #       &'a self,
+/// #       _request: &'a CountTokensRequest,
+/// #       _model: &'a Model,
+/// #       _options: &'a RequestOptions,
+/// #   ) -> BoxFuture<'a, CountTokensResponse> {
+/// #       Box::pin(async { Err(gemini_rs::error::Error::NoCandidatesError) })
+/// #   }
+/// #   fn text_embeddings<'a>(
+/// #       &'a self,
+/// #       _request: &'a TextEmbeddingRequest,
+/// #       _model: &'a Model,
+/// #       _options: &'a RequestOptions,
+/// #   ) -> BoxFuture<'a, TextEmbeddingResponse> {
+/// #       Box::pin(async { Err(gemini_rs::error::Error::NoCandidatesError) })
+/// #   }
+/// }
+///
+/// let client: Box<dyn GenerativeClient> = Box::new(Fake);
+/// ```
+pub trait GenerativeClient: Send + Sync {
+    fn generate_content<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, GenerateContentResponseResult>;
+
+    /// Streams the response. By default, the whole response is returned as a single item by
+    /// [`GenerativeClient::generate_content`], which is convenient for fakes.
+    fn generate_content_stream<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, BoxStream<'static, GenerateContentResponseResult>> {
+        Box::pin(async move {
+            let response = self.generate_content(request, model, options).await;
+            let stream: BoxStream<'static, _> = Box::pin(tokio_stream::once(response));
+            Ok(stream)
+        })
+    }
+
+    fn count_tokens<'a>(
+        &'a self,
+        request: &'a CountTokensRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, CountTokensResponse>;
+
+    fn text_embeddings<'a>(
+        &'a self,
+        request: &'a TextEmbeddingRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, TextEmbeddingResponse>;
+}
+
+impl<T: TokenProvider + Send + Sync> GenerativeClient for GeminiClient<T> {
+    fn generate_content<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, GenerateContentResponseResult> {
+        Box::pin(self.generate_content_with_options(request, model, options))
+    }
+
+    fn generate_content_stream<'a>(
+        &'a self,
+        request: &'a GenerateContentRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, BoxStream<'static, GenerateContentResponseResult>> {
+        Box::pin(async move {
+            let stream = self
+                .generate_content_stream_with_options(request, model, options)
+                .await?;
+            let stream: BoxStream<'static, _> = Box::pin(stream);
+            Ok(stream)
+        })
+    }
+
+    fn count_tokens<'a>(
+        &'a self,
+        request: &'a CountTokensRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, CountTokensResponse> {
+        Box::pin(self.count_tokens_with_options(request, model, options))
+    }
+
+    fn text_embeddings<'a>(
+        &'a self,
+        request: &'a TextEmbeddingRequest,
+        model: &'a Model,
+        options: &'a RequestOptions,
+    ) -> BoxFuture<'a, TextEmbeddingResponse> {
+        Box::pin(self.text_embeddings_with_options(request, model, options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::GenerativeClient;
+    use crate::prelude::{
+        Backend, Content, GeminiClient, GenerateContentRequest, Model, RequestOptions,
+    };
+    use crate::test_support::{FakeToken, MockResponse, MockServer};
+
+    #[tokio::test]
+    async fn calls_the_gemini_client_through_the_trait() {
+        let server = MockServer::start(vec![MockResponse::json(
+            200,
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hello"}]}}]}"#,
+        )])
+        .await;
+        let client: Arc<dyn GenerativeClient> = Arc::new(
+            GeminiClient::builder(FakeToken, Backend::AiStudio)
+                .base_url(server.url())
+                .build(),
+        );
+
+        let request = GenerateContentRequest::builder()
+            .contents(vec![Content::builder().add_text_part("Hi").build()])
+            .build();
+        let model = Model::GEMINI_2_0_FLASH_001;
+        let options = RequestOptions::default();
+        let response = client
+            .generate_content(&request, &model, &options)
+            .await
+            .unwrap();
+        assert_eq!(response.candidates[0].get_text().unwrap(), "Hello");
+        assert_eq!(
+            server.requests()[0].path,
+            "/v1beta/models/gemini-2.0-flash-001:generateContent"
+        );
+    }
+}
@@ -1,12 +1,44 @@
+mod backend;
 mod client;
 mod dialogue;
 pub mod error;
+mod events;
+mod failover;
+mod generative;
+mod iam;
+mod model;
+mod rate_limit;
+mod request_options;
+mod retry;
+mod service;
+mod sse;
+mod stream;
+#[cfg(test)]
+mod test_support;
+mod timeout;
+mod token_cache;
 mod token_provider;
 mod types;
+mod usage;
+mod workload_identity;
 
 pub mod prelude {
+    pub use crate::backend::*;
     pub use crate::client::*;
     pub use crate::dialogue::*;
+    pub use crate::events::{StreamEvent, StreamEvents};
+    pub use crate::generative::*;
+    pub use crate::iam::ImpersonatedTokenProvider;
+    pub use crate::model::*;
+    pub use crate::rate_limit::{RateLimit, RateLimiter};
+    pub use crate::request_options::*;
+    pub use crate::retry::RetryPolicy;
+    pub use crate::sse::{SseBody, SsePayload};
+    pub use crate::stream::{ResponseStream, StreamAccumulator};
+    pub use crate::timeout::{TimeoutKind, Timeouts};
+    pub use crate::token_cache::{CachingTokenProvider, TokenCacheMetrics};
     pub use crate::token_provider::*;
     pub use crate::types::*;
+    pub use crate::usage::{ModelPrice, PriceTable, TokenUsage, UsageTracker};
+    pub use crate::workload_identity::*;
 }
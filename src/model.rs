@@ -0,0 +1,411 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+use crate::error::{Error, Result};
+use crate::rate_limit::estimate_tokens;
+use crate::types::{GenerateContentRequest, Part};
+
+/// A kind of input a model accepts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Modality {
+    Text,
+    Image,
+    Audio,
+    Video,
+    /// PDF and plain text documents.
+    Document,
+}
+
+impl Modality {
+    /// The modality of content with the given MIME type, if it's one models accept.
+    pub fn from_mime_type(mime_type: &str) -> Option<Modality> {
+        match mime_type.split('/').next()? {
+            "text" => Some(Modality::Document),
+            "image" => Some(Modality::Image),
+            "audio" => Some(Modality::Audio),
+            "video" => Some(Modality::Video),
+            _ if mime_type == "application/pdf" => Some(Modality::Document),
+            _ => None,
+        }
+    }
+}
+
+const TEXT: &[Modality] = &[Modality::Text];
+const MULTIMODAL: &[Modality] = &[
+    Modality::Text,
+    Modality::Image,
+    Modality::Audio,
+    Modality::Video,
+    Modality::Document,
+];
+
+/// What a model supports, used to reject requests it can't serve before sending them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Checked against a rough estimate of the prompt tokens, at four characters per token.
+    pub max_input_tokens: u32,
+    /// `0` for models that don't generate text, such as embedding models.
+    pub max_output_tokens: u32,
+    pub input_modalities: &'static [Modality],
+    /// Whether `response_schema` can be set in the generation config.
+    pub response_schema: bool,
+}
+
+impl ModelCapabilities {
+    const fn gemini(max_input_tokens: u32, input_modalities: &'static [Modality]) -> Self {
+        ModelCapabilities {
+            max_input_tokens,
+            max_output_tokens: 8192,
+            input_modalities,
+            response_schema: true,
+        }
+    }
+
+    const fn text_input(max_input_tokens: u32) -> Self {
+        ModelCapabilities {
+            max_input_tokens,
+            max_output_tokens: 0,
+            input_modalities: TEXT,
+            response_schema: false,
+        }
+    }
+}
+
+/// How a [`Model`] is identified in request URLs.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ModelName {
+    /// The ID of a model served by the publisher set on the client, e.g.
+    /// `gemini-2.0-flash-001`.
+    Id(Cow<'static, str>),
+    /// A model served by a given publisher on Vertex AI, e.g. `meta` or `anthropic`.
+    Publisher { publisher: String, id: String },
+    /// The ID of a Vertex AI endpoint a model is deployed to, such as a tuned model, in the
+    /// client's project and location.
+    Endpoint(String),
+    /// A full resource name, sent as is, e.g.
+    /// `projects/my-project/locations/us-central1/endpoints/1234` for a model tuned on Vertex
+    /// AI, or `tunedModels/my-model` on AI Studio.
+    ResourceName(String),
+}
+
+/// A model to send requests to, along with its capabilities when they're known.
+///
+/// Every client method accepting a model takes an `impl Into<Model>`, so a string can be used
+/// as well: `endpoints/{id}` and `publishers/{publisher}/models/{id}` are parsed as such,
+/// other strings containing a `/` are taken as resource names and the rest as model IDs. The
+/// capabilities of the IDs in the constants below are looked up.
+///
+/// ```
+/// # use gemini_rs::prelude::*;
+/// let flash = Model::GEMINI_2_0_FLASH_001;
+/// assert_eq!(Model::from("gemini-2.0-flash-001"), flash);
+///
+/// let tuned = Model::resource_name("projects/my-project/locations/us-central1/endpoints/1234")
+///     .with_capabilities(*flash.capabilities().unwrap());
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Model {
+    name: ModelName,
+    capabilities: Option<ModelCapabilities>,
+}
+
+impl Model {
+    pub const GEMINI_1_0_PRO_002: Model = Model::known(
+        "gemini-1.0-pro-002",
+        ModelCapabilities {
+            max_input_tokens: 32_760,
+            max_output_tokens: 8192,
+            input_modalities: TEXT,
+            response_schema: false,
+        },
+    );
+    pub const GEMINI_1_5_FLASH_001: Model = Model::known(
+        "gemini-1.5-flash-001",
+        ModelCapabilities::gemini(1_048_576, MULTIMODAL),
+    );
+    pub const GEMINI_1_5_FLASH_002: Model = Model::known(
+        "gemini-1.5-flash-002",
+        ModelCapabilities::gemini(1_048_576, MULTIMODAL),
+    );
+    pub const GEMINI_1_5_PRO_001: Model = Model::known(
+        "gemini-1.5-pro-001",
+        ModelCapabilities::gemini(2_097_152, MULTIMODAL),
+    );
+    pub const GEMINI_1_5_PRO_002: Model = Model::known(
+        "gemini-1.5-pro-002",
+        ModelCapabilities::gemini(2_097_152, MULTIMODAL),
+    );
+    pub const GEMINI_2_0_FLASH_001: Model = Model::known(
+        "gemini-2.0-flash-001",
+        ModelCapabilities::gemini(1_048_576, MULTIMODAL),
+    );
+    pub const GEMINI_2_0_FLASH_LITE_001: Model = Model::known(
+        "gemini-2.0-flash-lite-001",
+        ModelCapabilities::gemini(1_048_576, MULTIMODAL),
+    );
+    pub const TEXT_EMBEDDING_004: Model =
+        Model::known("text-embedding-004", ModelCapabilities::text_input(2048));
+    pub const TEXT_EMBEDDING_005: Model =
+        Model::known("text-embedding-005", ModelCapabilities::text_input(2048));
+    pub const TEXTEMBEDDING_GECKO_003: Model = Model::known(
+        "textembedding-gecko@003",
+        ModelCapabilities::text_input(3072),
+    );
+    pub const IMAGEGENERATION_006: Model =
+        Model::known("imagegeneration@006", ModelCapabilities::text_input(480));
+    pub const IMAGEN_3_0_GENERATE_001: Model = Model::known(
+        "imagen-3.0-generate-001",
+        ModelCapabilities::text_input(480),
+    );
+    pub const IMAGEN_3_0_FAST_GENERATE_001: Model = Model::known(
+        "imagen-3.0-fast-generate-001",
+        ModelCapabilities::text_input(480),
+    );
+
+    const KNOWN: &'static [Model] = &[
+        Model::GEMINI_1_0_PRO_002,
+        Model::GEMINI_1_5_FLASH_001,
+        Model::GEMINI_1_5_FLASH_002,
+        Model::GEMINI_1_5_PRO_001,
+        Model::GEMINI_1_5_PRO_002,
+        Model::GEMINI_2_0_FLASH_001,
+        Model::GEMINI_2_0_FLASH_LITE_001,
+        Model::TEXT_EMBEDDING_004,
+        Model::TEXT_EMBEDDING_005,
+        Model::TEXTEMBEDDING_GECKO_003,
+        Model::IMAGEGENERATION_006,
+        Model::IMAGEN_3_0_GENERATE_001,
+        Model::IMAGEN_3_0_FAST_GENERATE_001,
+    ];
+
+    const fn known(id: &'static str, capabilities: ModelCapabilities) -> Self {
+        Model {
+            name: ModelName::Id(Cow::Borrowed(id)),
+            capabilities: Some(capabilities),
+        }
+    }
+
+    /// A model served by the client's publisher, such as a newer model without a constant or
+    /// a model from another publisher. Its capabilities are looked up if the ID is known.
+    pub fn id<I: Into<String>>(id: I) -> Self {
+        let id = id.into();
+        let capabilities = Model::KNOWN
+            .iter()
+            .find(|model| matches!(&model.name, ModelName::Id(known) if *known == id))
+            .and_then(|model| model.capabilities);
+        Model {
+            name: ModelName::Id(Cow::Owned(id)),
+            capabilities,
+        }
+    }
+
+    /// A model served by `publisher` on Vertex AI, regardless of the publisher set on the
+    /// client.
+    pub fn publisher_model<P: Into<String>, I: Into<String>>(publisher: P, id: I) -> Self {
+        Model {
+            name: ModelName::Publisher {
+                publisher: publisher.into(),
+                id: id.into(),
+            },
+            capabilities: None,
+        }
+    }
+
+    /// A model deployed to a Vertex AI endpoint of the client's project and location, such as a
+    /// tuned model. Like a full resource name, requests to it don't fail over to the fallback
+    /// locations, as an endpoint only exists in one location.
+    pub fn endpoint<I: Into<String>>(id: I) -> Self {
+        Model {
+            name: ModelName::Endpoint(id.into()),
+            capabilities: None,
+        }
+    }
+
+    /// A model identified by its full resource name. Its capabilities are unknown unless set
+    /// with [`Model::with_capabilities`].
+    pub fn resource_name<N: Into<String>>(name: N) -> Self {
+        Model {
+            name: ModelName::ResourceName(name.into()),
+            capabilities: None,
+        }
+    }
+
+    /// Sets the capabilities of the model, e.g. the ones of the base model of a tuned model.
+    pub fn with_capabilities(mut self, capabilities: ModelCapabilities) -> Self {
+        self.capabilities = Some(capabilities);
+        self
+    }
+
+    pub fn name(&self) -> &ModelName {
+        &self.name
+    }
+
+    pub fn capabilities(&self) -> Option<&ModelCapabilities> {
+        self.capabilities.as_ref()
+    }
+
+    /// Checks that the model supports the request, failing with [`Error::UnsupportedByModel`]
+    /// if it doesn't. Always succeeds when the capabilities of the model aren't known.
+    pub fn validate(&self, request: &GenerateContentRequest) -> Result<()> {
+        let Some(capabilities) = &self.capabilities else {
+            return Ok(());
+        };
+        let unsupported =
+            |reason: String| Err(Error::UnsupportedByModel(format!("{self}: {reason}")));
+
+        let input_tokens = estimate_tokens(request);
+        if input_tokens > capabilities.max_input_tokens {
+            return unsupported(format!(
+                "the request is estimated at {input_tokens} input tokens, the maximum is {}",
+                capabilities.max_input_tokens
+            ));
+        }
+        if let Some(generation_config) = &request.generation_config {
+            if generation_config.response_schema.is_some() && !capabilities.response_schema {
+                return unsupported("response_schema is not supported".to_string());
+            }
+            if let Some(max_output_tokens) = generation_config.max_output_tokens {
+                if max_output_tokens > capabilities.max_output_tokens as i32 {
+                    return unsupported(format!(
+                        "max_output_tokens is {max_output_tokens}, the maximum is {}",
+                        capabilities.max_output_tokens
+                    ));
+                }
+            }
+        }
+
+        let mime_types = request
+            .contents
+            .iter()
+            .chain(request.system_instruction.iter())
+            .filter_map(|content| content.parts.as_ref())
+            .flatten()
+            .filter_map(|part| match part {
+                Part::InlineData { mime_type, .. } | Part::FileData { mime_type, .. } => {
+                    Some(mime_type)
+                }
+                _ => None,
+            });
+        for mime_type in mime_types {
+            let supported = Modality::from_mime_type(mime_type)
+                .is_some_and(|modality| capabilities.input_modalities.contains(&modality));
+            if !supported {
+                return unsupported(format!("{mime_type} input is not supported"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Model {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.name {
+            ModelName::Id(id) => f.write_str(id),
+            ModelName::Publisher { publisher, id } => {
+                write!(f, "publishers/{}/models/{}", publisher, id)
+            }
+            ModelName::Endpoint(id) => write!(f, "endpoints/{}", id),
+            ModelName::ResourceName(name) => f.write_str(name),
+        }
+    }
+}
+
+impl From<&str> for Model {
+    fn from(name: &str) -> Self {
+        let segments: Vec<_> = name.split('/').collect();
+        match segments[..] {
+            [id] => Model::id(id),
+            ["endpoints", id] => Model::endpoint(id),
+            ["publishers", publisher, "models", id] => Model::publisher_model(publisher, id),
+            _ => Model::resource_name(name),
+        }
+    }
+}
+
+impl From<String> for Model {
+    fn from(name: String) -> Self {
+        Model::from(name.as_str())
+    }
+}
+
+impl From<&String> for Model {
+    fn from(name: &String) -> Self {
+        Model::from(name.as_str())
+    }
+}
+
+impl From<&Model> for Model {
+    fn from(model: &Model) -> Self {
+        model.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Model, ModelCapabilities, ModelName};
+    use crate::error::Error;
+    use crate::prelude::{Content, GenerateContentRequest, GenerationConfig, Part, Role};
+
+    #[test]
+    fn parses_ids_and_resource_names() {
+        assert_eq!(Model::from("gemini-1.5-pro-002"), Model::GEMINI_1_5_PRO_002);
+        assert!(Model::from("gemini-next").capabilities().is_none());
+
+        let model = Model::from("projects/p/locations/l/endpoints/1234");
+        assert!(matches!(model.name(), ModelName::ResourceName(_)));
+        assert_eq!(model.to_string(), "projects/p/locations/l/endpoints/1234");
+
+        let model = Model::from("publishers/meta/models/llama");
+        assert_eq!(model, Model::publisher_model("meta", "llama"));
+        assert_eq!(Model::from("endpoints/1234"), Model::endpoint("1234"));
+    }
+
+    #[test]
+    fn rejects_requests_the_model_does_not_support() {
+        let request = GenerateContentRequest::builder()
+            .contents(vec![Content {
+                role: Some(Role::User),
+                parts: Some(vec![Part::InlineData {
+                    mime_type: "image/png".to_string(),
+                    data: String::new(),
+                }]),
+            }])
+            .generation_config(
+                GenerationConfig::builder()
+                    .response_schema(serde_json::json!({"type": "string"}))
+                    .build(),
+            )
+            .build();
+
+        assert!(Model::GEMINI_2_0_FLASH_001.validate(&request).is_ok());
+        assert!(Model::from("gemini-next").validate(&request).is_ok());
+        assert!(matches!(
+            Model::GEMINI_1_0_PRO_002.validate(&request),
+            Err(Error::UnsupportedByModel(_))
+        ));
+
+        let request = GenerateContentRequest::builder()
+            .contents(vec![Content {
+                role: Some(Role::User),
+                parts: Some(vec![Part::Text("a".repeat(40))]),
+            }])
+            .build();
+        let model = Model::from("gemini-small").with_capabilities(ModelCapabilities {
+            max_input_tokens: 10,
+            ..*Model::GEMINI_2_0_FLASH_001.capabilities().unwrap()
+        });
+
+        assert!(model.validate(&request).is_ok());
+        let request = GenerateContentRequest {
+            contents: vec![Content {
+                role: Some(Role::User),
+                parts: Some(vec![Part::Text("a".repeat(44))]),
+            }],
+            ..request
+        };
+        assert!(matches!(
+            model.validate(&request),
+            Err(Error::UnsupportedByModel(_))
+        ));
+    }
+}
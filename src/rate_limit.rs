@@ -0,0 +1,213 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::types::{GenerateContentRequest, Part};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Quota for a model. `None` leaves a dimension unlimited.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RateLimit {
+    /// A limit of zero is treated as one, letting a request through once the window is empty.
+    pub requests_per_minute: Option<u32>,
+    pub tokens_per_minute: Option<u32>,
+}
+
+/// Throttles requests on the client so they stay within per-model quotas.
+///
+/// Usage is tracked over a sliding one minute window. Calls that would exceed a limit wait
+/// until enough of the window has elapsed, rather than failing. Token usage is estimated from
+/// the request before it is sent and corrected with the `UsageMetadata` of the response.
+///
+/// The limiter is cheap to clone, and clones share their state, so one limiter can be shared by
+/// several clients using the same quota.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    default_limit: RateLimit,
+    model_limits: HashMap<String, RateLimit>,
+    usage: Arc<Mutex<HashMap<String, ModelUsage>>>,
+}
+
+#[derive(Debug, Default)]
+struct ModelUsage {
+    requests: VecDeque<Instant>,
+    tokens: VecDeque<(Instant, i64)>,
+}
+
+impl ModelUsage {
+    fn prune(&mut self, now: Instant) {
+        while matches!(self.requests.front(), Some(time) if now - *time >= WINDOW) {
+            self.requests.pop_front();
+        }
+        while matches!(self.tokens.front(), Some((time, _)) if now - *time >= WINDOW) {
+            self.tokens.pop_front();
+        }
+    }
+
+    fn token_count(&self) -> i64 {
+        self.tokens.iter().map(|(_, tokens)| tokens).sum()
+    }
+
+    /// How long to wait before a request using `tokens` fits in the limit, if it doesn't now.
+    fn wait_time(&self, limit: &RateLimit, tokens: u32, now: Instant) -> Option<Duration> {
+        let mut wait = None;
+        if let Some(rpm) = limit.requests_per_minute {
+            let rpm = rpm.max(1) as usize;
+            if let Some(time) = self
+                .requests
+                .len()
+                .checked_sub(rpm)
+                .and_then(|index| self.requests.get(index))
+            {
+                wait = Some(WINDOW - (now - *time));
+            }
+        }
+        if let Some(tpm) = limit.tokens_per_minute {
+            // A request larger than the whole quota is let through once the window is empty.
+            let mut excess = self.token_count() + tokens as i64 - tpm as i64;
+            let mut entries = self.tokens.iter();
+            while excess > 0 {
+                let Some((time, tokens)) = entries.next() else {
+                    break;
+                };
+                excess -= tokens;
+                let entry_wait = WINDOW - (now - *time);
+                wait = Some(wait.map_or(entry_wait, |wait: Duration| wait.max(entry_wait)));
+            }
+        }
+        wait
+    }
+}
+
+impl RateLimiter {
+    /// Creates a limiter applying `default_limit` to every model without a specific limit.
+    pub fn new(default_limit: RateLimit) -> Self {
+        RateLimiter {
+            default_limit,
+            model_limits: HashMap::new(),
+            usage: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn with_model_limit<M: Into<String>>(mut self, model: M, limit: RateLimit) -> Self {
+        self.model_limits.insert(model.into(), limit);
+        self
+    }
+
+    /// Waits until a request to `model`, estimated to use `estimated_tokens`, fits within its
+    /// quota, and records it.
+    pub async fn acquire(&self, model: &str, estimated_tokens: u32) {
+        let limit = self.limit(model);
+        loop {
+            let wait = {
+                let mut usage = self.usage.lock().unwrap();
+                let usage = usage.entry(model.to_string()).or_default();
+                let now = Instant::now();
+                usage.prune(now);
+                match usage.wait_time(&limit, estimated_tokens, now) {
+                    Some(wait) => wait,
+                    None => {
+                        usage.requests.push_back(now);
+                        usage.tokens.push_back((now, estimated_tokens as i64));
+                        return;
+                    }
+                }
+            };
+            tracing::debug!(model, ?wait, "Rate limit reached, waiting");
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Corrects the token usage recorded by [`RateLimiter::acquire`] once the actual usage of
+    /// the request is known.
+    pub fn record_usage(&self, model: &str, estimated_tokens: u32, actual_tokens: u32) {
+        let mut usage = self.usage.lock().unwrap();
+        let usage = usage.entry(model.to_string()).or_default();
+        usage.tokens.push_back((
+            Instant::now(),
+            actual_tokens as i64 - estimated_tokens as i64,
+        ));
+    }
+
+    fn limit(&self, model: &str) -> RateLimit {
+        self.model_limits
+            .get(model)
+            .copied()
+            .unwrap_or(self.default_limit)
+    }
+}
+
+/// Roughly estimates the prompt tokens of a request, at four characters per token.
+pub(crate) fn estimate_tokens(request: &GenerateContentRequest) -> u32 {
+    let characters: usize = request
+        .contents
+        .iter()
+        .chain(request.system_instruction.iter())
+        .filter_map(|content| content.parts.as_ref())
+        .flatten()
+        .map(|part| match part {
+            Part::Text(text) => text.len(),
+            _ => 0,
+        })
+        .sum();
+    (characters / 4) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::{ModelUsage, RateLimit};
+
+    #[test]
+    fn waits_for_the_oldest_entries_to_leave_the_window() {
+        let start = Instant::now();
+        let mut usage = ModelUsage::default();
+        usage
+            .requests
+            .extend([start, start + Duration::from_secs(10)]);
+        usage
+            .tokens
+            .extend([(start, 600), (start + Duration::from_secs(10), 300)]);
+        let now = start + Duration::from_secs(20);
+
+        let rpm = RateLimit {
+            requests_per_minute: Some(2),
+            tokens_per_minute: None,
+        };
+        assert_eq!(usage.wait_time(&rpm, 0, now), Some(Duration::from_secs(40)));
+
+        let tpm = RateLimit {
+            requests_per_minute: None,
+            tokens_per_minute: Some(1000),
+        };
+        assert_eq!(usage.wait_time(&tpm, 100, now), None);
+        assert_eq!(
+            usage.wait_time(&tpm, 200, now),
+            Some(Duration::from_secs(40))
+        );
+        assert_eq!(
+            usage.wait_time(&tpm, 800, now),
+            Some(Duration::from_secs(50))
+        );
+    }
+
+    #[test]
+    fn treats_a_zero_request_limit_as_one() {
+        let start = Instant::now();
+        let zero = RateLimit {
+            requests_per_minute: Some(0),
+            tokens_per_minute: None,
+        };
+        let mut usage = ModelUsage::default();
+        assert_eq!(usage.wait_time(&zero, 0, start), None);
+
+        usage.requests.push_back(start);
+        let now = start + Duration::from_secs(20);
+        assert_eq!(
+            usage.wait_time(&zero, 0, now),
+            Some(Duration::from_secs(40))
+        );
+    }
+}
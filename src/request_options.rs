@@ -0,0 +1,82 @@
+use std::collections::HashMap;
+
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+
+use crate::retry::RetryPolicy;
+use crate::timeout::Timeouts;
+
+/// Per-call settings overriding the client-wide configuration.
+#[derive(Clone, Debug, Default)]
+pub struct RequestOptions {
+    pub retry_policy: Option<RetryPolicy>,
+    /// Timeouts for the call. Unset fields fall back to the client's timeouts.
+    pub timeouts: Option<Timeouts>,
+    /// Headers sent with the call, replacing the client's headers with the same name.
+    pub headers: HeaderMap,
+    /// Labels added to content generation requests, see
+    /// [`crate::prelude::GeminiClientBuilder::label`].
+    pub labels: HashMap<String, String>,
+    /// Overrides [`crate::prelude::GeminiClientBuilder::user_project`].
+    pub user_project: Option<String>,
+    /// Overrides [`crate::prelude::GeminiClientBuilder::scopes`].
+    pub scopes: Option<Vec<String>>,
+    /// The caller tag the usage of the call is recorded under, see
+    /// [`crate::prelude::UsageTracker`].
+    pub usage_tag: Option<String>,
+}
+
+impl RequestOptions {
+    pub fn builder() -> RequestOptionsBuilder {
+        RequestOptionsBuilder::default()
+    }
+}
+
+#[derive(Default)]
+pub struct RequestOptionsBuilder {
+    options: RequestOptions,
+}
+
+impl RequestOptionsBuilder {
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.options.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.options.timeouts = Some(timeouts);
+        self
+    }
+
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.options.headers.insert(name, value);
+        self
+    }
+
+    pub fn label<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.options.labels.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn user_project<P: Into<String>>(mut self, user_project: P) -> Self {
+        self.options.user_project = Some(user_project.into());
+        self
+    }
+
+    pub fn scopes<I, S>(mut self, scopes: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.options.scopes = Some(scopes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn usage_tag<S: Into<String>>(mut self, usage_tag: S) -> Self {
+        self.options.usage_tag = Some(usage_tag.into());
+        self
+    }
+
+    pub fn build(self) -> RequestOptions {
+        self.options
+    }
+}
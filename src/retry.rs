@@ -0,0 +1,126 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use reqwest::{header::RETRY_AFTER, StatusCode};
+
+use crate::error::{Error, HttpStatusError};
+use crate::timeout::TimeoutKind;
+use crate::types::ErrorType;
+
+/// Controls how failed requests are retried.
+///
+/// Requests are retried when the API responds with `429 Too Many Requests` or a `5xx` status,
+/// or when the connection fails. The delay between attempts grows exponentially from
+/// `initial_backoff` up to `max_backoff`, with a random `jitter` fraction removed from it. When
+/// the server asks for a specific delay, through the `Retry-After` header or a
+/// `google.rpc.RetryInfo` error detail, that delay is used instead.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// The total number of attempts, including the first one. `1` disables retries.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// The fraction of the backoff, between 0 and 1, that may be randomly removed from it.
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// A policy that never retries. This is the client default.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(32) as i32;
+        let backoff = self
+            .initial_backoff
+            .mul_f64(self.multiplier.max(1.0).powi(exponent))
+            .min(self.max_backoff);
+        let jitter = self.jitter.clamp(0.0, 1.0) * random_fraction();
+        backoff.mul_f64(1.0 - jitter)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+pub(crate) fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+pub(crate) fn is_retryable_error(error: &Error) -> bool {
+    match error {
+        Error::HttpClient(e) => e.is_connect() || e.is_timeout(),
+        Error::Timeout(TimeoutKind::Connect) => true,
+        _ => false,
+    }
+}
+
+/// Returns the delay requested by the server for a failed response, if any.
+pub(crate) fn server_retry_delay(error: &HttpStatusError) -> Option<Duration> {
+    let retry_after = error
+        .headers
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .map(Duration::from_secs);
+    if retry_after.is_some() {
+        return retry_after;
+    }
+
+    let details = error.vertex_error.as_ref()?.details.as_ref()?;
+    details.iter().find_map(|detail| match detail {
+        ErrorType::RetryInfo { retry_delay } => parse_duration(retry_delay),
+        _ => None,
+    })
+}
+
+/// Parses a protobuf JSON `Duration`, such as `"30s"` or `"0.5s"`.
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let seconds = duration.strip_suffix('s')?.parse::<f64>().ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+fn random_fraction() -> f64 {
+    let random = RandomState::new().build_hasher().finish();
+    (random >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_duration, RetryPolicy};
+
+    #[test]
+    fn backs_off_exponentially_up_to_the_maximum() {
+        let policy = RetryPolicy {
+            jitter: 0.0,
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(1), Duration::from_millis(500));
+        assert_eq!(policy.backoff(3), Duration::from_secs(2));
+        assert_eq!(policy.backoff(20), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn parses_retry_info_delays() {
+        assert_eq!(parse_duration("30s"), Some(Duration::from_secs(30)));
+        assert_eq!(parse_duration("0.5s"), Some(Duration::from_millis(500)));
+        assert_eq!(parse_duration("soon"), None);
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt::Debug;
+
+use tower::util::BoxCloneSyncService;
+use tower::{BoxError, Service, ServiceExt};
+
+use crate::error::{Error, Result};
+use crate::timeout::TimeoutKind;
+
+/// The [`tower::Service`] stack every HTTP request made by the client goes through.
+#[derive(Clone)]
+pub(crate) struct HttpService(BoxCloneSyncService<reqwest::Request, reqwest::Response, BoxError>);
+
+impl HttpService {
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<reqwest::Request, Response = reqwest::Response> + Clone + Send + Sync + 'static,
+        S::Error: Into<BoxError>,
+        S::Future: Send + 'static,
+    {
+        HttpService(BoxCloneSyncService::new(service.map_err(Into::into)))
+    }
+
+    pub async fn call(&self, request: reqwest::Request) -> Result<reqwest::Response> {
+        let mut service = self.0.clone();
+        let service = service.ready().await.map_err(into_error)?;
+        service.call(request).await.map_err(into_error)
+    }
+}
+
+impl Debug for HttpService {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HttpService")
+    }
+}
+
+/// Keeps errors from the `reqwest` client distinguishable from the ones raised by middleware.
+fn into_error(e: BoxError) -> Error {
+    match e.downcast::<reqwest::Error>() {
+        Ok(e) if e.is_connect() && e.is_timeout() => Error::Timeout(TimeoutKind::Connect),
+        Ok(e) => Error::HttpClient(*e),
+        Err(e) => Error::Middleware(e),
+    }
+}
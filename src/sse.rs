@@ -0,0 +1,256 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::{Instant, Sleep};
+use tokio_stream::Stream;
+
+use crate::error::Result;
+use crate::types::GenerateContentResponseResult;
+
+/// What the `data` of the events sent for each response contains.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SsePayload {
+    /// The response, serialized as JSON like the Gemini API does.
+    #[default]
+    Json,
+    /// The text of the first candidate. Responses without text aren't sent.
+    Text,
+}
+
+/// Re-encodes a stream of responses, such as a [`crate::prelude::ResponseStream`], as a
+/// server-sent events body, e.g. to proxy it to browsers.
+///
+/// Each response is sent as an event named [`SseBody::event_name`], if set, or as an unnamed
+/// event otherwise. An error is sent as an `error` event whose data is a JSON object with a
+/// `message`, and ends the body. When the stream ends without error, a `done` event with
+/// `[DONE]` as data is sent, unless disabled with [`SseBody::done_event`].
+///
+/// The body yields [`Bytes`] and never fails, so it can be given to
+/// `axum::body::Body::from_stream` or wrapped in a `http_body_util::StreamBody`:
+///
+/// ```ignore
+/// async fn chat(State(client): State<GeminiClient<ApiKey>>) -> impl IntoResponse {
+///     let stream = client.generate_content_stream(&request, model).await.unwrap();
+///     let body = SseBody::new(stream).heartbeat(Duration::from_secs(15));
+///     ([(CONTENT_TYPE, SseBody::<ResponseStream>::CONTENT_TYPE)], Body::from_stream(body))
+/// }
+/// ```
+pub struct SseBody<S> {
+    inner: S,
+    payload: SsePayload,
+    event_name: Option<String>,
+    done_event: Option<String>,
+    heartbeat: Option<Duration>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    done: bool,
+}
+
+impl<S> SseBody<S> {
+    /// The content type of server-sent events.
+    pub const CONTENT_TYPE: &'static str = "text/event-stream";
+
+    pub fn new(inner: S) -> Self {
+        SseBody {
+            inner,
+            payload: SsePayload::Json,
+            event_name: None,
+            done_event: Some("done".to_string()),
+            heartbeat: None,
+            sleep: None,
+            done: false,
+        }
+    }
+
+    pub fn payload(mut self, payload: SsePayload) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    /// Sets the name of the events sent for responses.
+    pub fn event_name<N: Into<String>>(mut self, event_name: N) -> Self {
+        self.event_name = Some(event_name.into());
+        self
+    }
+
+    /// Sets the name of the event sent when the stream ends, or disables it with `None`.
+    pub fn done_event<N: Into<String>>(mut self, done_event: Option<N>) -> Self {
+        self.done_event = done_event.map(Into::into);
+        self
+    }
+
+    /// Sends a comment after `interval` without events, which keeps proxies and load
+    /// balancers from closing idle connections.
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    fn reset_heartbeat(&mut self) {
+        if let (Some(sleep), Some(interval)) = (&mut self.sleep, self.heartbeat) {
+            sleep.as_mut().reset(Instant::now() + interval);
+        }
+    }
+
+    /// The event for a response, if it has something to send.
+    fn response_event(&self, response: &GenerateContentResponseResult) -> Option<Bytes> {
+        let event_name = self.event_name.as_deref();
+        match self.payload {
+            SsePayload::Json => match serde_json::to_string(response) {
+                Ok(json) => Some(event(event_name, &json)),
+                Err(e) => Some(error_event(&e.to_string())),
+            },
+            SsePayload::Text => response
+                .candidates
+                .first()
+                .and_then(|candidate| candidate.get_text())
+                .filter(|text| !text.is_empty())
+                .map(|text| event(event_name, &text)),
+        }
+    }
+}
+
+impl<S> Stream for SseBody<S>
+where
+    S: Stream<Item = Result<GenerateContentResponseResult>> + Unpin,
+{
+    type Item = std::result::Result<Bytes, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        if this.sleep.is_none() {
+            if let Some(interval) = this.heartbeat {
+                this.sleep = Some(Box::pin(tokio::time::sleep(interval)));
+            }
+        }
+
+        while !this.done {
+            let frame = match Pin::new(&mut this.inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(response))) => match this.response_event(&response) {
+                    Some(frame) => frame,
+                    None => continue,
+                },
+                Poll::Ready(Some(Err(e))) => {
+                    this.done = true;
+                    error_event(&e.to_string())
+                }
+                Poll::Ready(None) => {
+                    this.done = true;
+                    match &this.done_event {
+                        Some(done_event) => event(Some(done_event), "[DONE]"),
+                        None => break,
+                    }
+                }
+                Poll::Pending => {
+                    if let Some(sleep) = &mut this.sleep {
+                        if sleep.as_mut().poll(cx).is_ready() {
+                            this.reset_heartbeat();
+                            return Poll::Ready(Some(Ok(Bytes::from_static(b": keep-alive\n\n"))));
+                        }
+                    }
+                    return Poll::Pending;
+                }
+            };
+            this.reset_heartbeat();
+            return Poll::Ready(Some(Ok(frame)));
+        }
+        Poll::Ready(None)
+    }
+}
+
+fn event(name: Option<&str>, data: &str) -> Bytes {
+    let mut frame = String::new();
+    if let Some(name) = name {
+        let _ = writeln!(frame, "event: {}", name);
+    }
+    for line in data.split('\n') {
+        let _ = writeln!(frame, "data: {}", line.trim_end_matches('\r'));
+    }
+    frame.push('\n');
+    Bytes::from(frame)
+}
+
+fn error_event(message: &str) -> Bytes {
+    event(
+        Some("error"),
+        &serde_json::json!({ "message": message }).to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio_stream::{Stream, StreamExt};
+
+    use super::{SseBody, SsePayload};
+    use crate::error::{Error, Result};
+    use crate::types::{GenerateContentResponse, GenerateContentResponseResult};
+
+    fn response(json: &str) -> Result<GenerateContentResponseResult> {
+        serde_json::from_str::<GenerateContentResponse>(json)
+            .unwrap()
+            .into_result()
+    }
+
+    async fn body_text<S>(body: SseBody<S>) -> String
+    where
+        S: Stream<Item = Result<GenerateContentResponseResult>> + Unpin,
+    {
+        let frames: Vec<_> = body.map(std::result::Result::unwrap).collect().await;
+        String::from_utf8(frames.concat()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn encodes_responses_as_events() {
+        let responses = vec![
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hi"}]}}]}"#,
+            ),
+            response(r#"{"candidates": [{"finishReason": "STOP"}]}"#),
+        ];
+        let body = SseBody::new(tokio_stream::iter(responses));
+        assert_eq!(
+            body_text(body).await,
+            concat!(
+                r#"data: {"candidates":[{"content":{"role":"model","parts":[{"text":"Hi"}]}}]}"#,
+                "\n\n",
+                r#"data: {"candidates":[{"finishReason":"STOP"}]}"#,
+                "\n\nevent: done\ndata: [DONE]\n\n",
+            )
+        );
+
+        let responses = vec![
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "a\nb"}]}}]}"#,
+            ),
+            response(r#"{"candidates": [{"finishReason": "STOP"}]}"#),
+            Err(Error::EventSourceClosedError),
+        ];
+        let body = SseBody::new(tokio_stream::iter(responses))
+            .payload(SsePayload::Text)
+            .event_name("delta");
+        assert_eq!(
+            body_text(body).await,
+            "event: delta\ndata: a\ndata: b\n\n\
+             event: error\ndata: {\"message\":\"EventSource closed error\"}\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_heartbeats_while_idle() {
+        let mut body =
+            SseBody::new(tokio_stream::pending::<Result<GenerateContentResponseResult>>())
+                .heartbeat(Duration::from_millis(20));
+        let frame = tokio::time::timeout(Duration::from_secs(1), body.next())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame, ": keep-alive\n\n");
+    }
+}
@@ -0,0 +1,415 @@
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio_stream::{Stream, StreamExt};
+
+use crate::error::{Error, Result};
+use crate::events::StreamEvents;
+use crate::generative::{BoxFuture, BoxStream};
+use crate::timeout::{TimeoutKind, TimeoutStream};
+use crate::types::{Candidate, Content, GenerateContentResponseResult, Part, UsageMetadata};
+
+/// The responses of a streamed `generateContent` call, as returned by
+/// [`crate::prelude::GeminiClient::generate_content_stream`].
+///
+/// The stream ends once every candidate has a `finishReason`. Errors are yielded as
+/// items, and end the stream as well: a failed event, a timeout, or a connection closed by the
+/// server before the response was finished, which yields [`Error::EventSourceClosedError`].
+///
+/// Events are read from the connection as the stream is polled, so a slow consumer slows down
+/// the reading of the response rather than buffering it.
+///
+/// Streams returned by [`crate::prelude::GeminiClient::generate_content_stream_resumable`]
+/// reconnect instead of failing when the connection drops, see there.
+pub struct ResponseStream {
+    inner: TimeoutStream<BoxStream<'static, GenerateContentResponseResult>>,
+    finished: FinishedCandidates,
+    done: bool,
+    resume: Option<Resume>,
+}
+
+/// Opens a new stream continuing the text received so far.
+pub(crate) type Reopen = Box<dyn Fn(String) -> BoxFuture<'static, ResponseStream> + Send>;
+
+struct Resume {
+    attempts_left: u32,
+    /// The text of the first candidate received so far.
+    text: String,
+    reopen: Reopen,
+    reopening: Option<BoxFuture<'static, ResponseStream>>,
+}
+
+impl ResponseStream {
+    pub(crate) fn new(
+        inner: TimeoutStream<BoxStream<'static, GenerateContentResponseResult>>,
+    ) -> Self {
+        ResponseStream {
+            inner,
+            finished: FinishedCandidates::default(),
+            done: false,
+            resume: None,
+        }
+    }
+
+    /// Reconnects with `reopen`, up to `attempts` times, when the connection drops.
+    pub(crate) fn resumable(mut self, attempts: u32, reopen: Reopen) -> Self {
+        self.resume = Some(Resume {
+            attempts_left: attempts,
+            text: String::new(),
+            reopen,
+            reopening: None,
+        });
+        self
+    }
+
+    /// Turns the stream into events of its first candidate.
+    pub fn events(self) -> StreamEvents {
+        self.candidate_events(0)
+    }
+
+    /// Turns the stream into events of the candidate at `index`, when several candidates are
+    /// requested.
+    pub fn candidate_events(self, index: usize) -> StreamEvents {
+        StreamEvents::new(self, index)
+    }
+
+    /// Reads the whole stream, folding its responses into one with a [`StreamAccumulator`].
+    pub async fn collect_response(mut self) -> Result<GenerateContentResponseResult> {
+        let mut accumulator = StreamAccumulator::new();
+        while let Some(response) = self.next().await {
+            accumulator.push(response?);
+        }
+        Ok(accumulator.finish())
+    }
+}
+
+impl Stream for ResponseStream {
+    type Item = Result<GenerateContentResponseResult>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            if let Some(reopening) = this.resume.as_mut().and_then(|r| r.reopening.as_mut()) {
+                let reopened = ready!(reopening.as_mut().poll(cx));
+                this.resume.as_mut().unwrap().reopening = None;
+                match reopened {
+                    Ok(stream) => this.inner = stream.inner,
+                    Err(e) => {
+                        this.done = true;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+            }
+
+            let error = match ready!(Pin::new(&mut this.inner).poll_next(cx)) {
+                Some(Ok(response)) => {
+                    if let Some(resume) = &mut this.resume {
+                        if let Some(text) = response.candidates.first().and_then(|c| c.get_text()) {
+                            resume.text.push_str(&text);
+                        }
+                    }
+                    this.done = this.finished.update(&response);
+                    return Poll::Ready(Some(Ok(response)));
+                }
+                Some(Err(e)) => e,
+                None => Error::EventSourceClosedError,
+            };
+
+            match &mut this.resume {
+                Some(resume) if resume.attempts_left > 0 && is_disconnect(&error) => {
+                    tracing::warn!(error = %error, "Stream disconnected, resuming");
+                    resume.attempts_left -= 1;
+                    resume.reopening = Some((resume.reopen)(resume.text.clone()));
+                }
+                _ => {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(error)));
+                }
+            }
+        }
+    }
+}
+
+/// Tracks which candidates of a streamed response have a `finishReason`.
+#[derive(Debug, Default)]
+pub(crate) struct FinishedCandidates {
+    finished: Vec<bool>,
+}
+
+impl FinishedCandidates {
+    /// Records the candidates of `response`, returning whether every candidate seen so far has
+    /// finished.
+    pub(crate) fn update(&mut self, response: &GenerateContentResponseResult) -> bool {
+        for (position, candidate) in response.candidates.iter().enumerate() {
+            let index = candidate_index(position, candidate);
+            if self.finished.len() <= index {
+                self.finished.resize(index + 1, false);
+            }
+            self.finished[index] |= candidate.finish_reason.is_some();
+        }
+        !self.finished.is_empty() && self.finished.iter().all(|finished| *finished)
+    }
+}
+
+/// The index of the candidate at `position` in a response, which is only sent by the API when
+/// several candidates are requested.
+pub(crate) fn candidate_index(position: usize, candidate: &Candidate) -> usize {
+    candidate.index.map_or(position, |index| index as usize)
+}
+
+/// Whether the connection of a stream was lost, as opposed to the call failing.
+fn is_disconnect(error: &Error) -> bool {
+    matches!(
+        error,
+        Error::EventSourceError(_)
+            | Error::EventSourceClosedError
+            | Error::HttpClient(_)
+            | Error::Timeout(TimeoutKind::Idle)
+    )
+}
+
+/// Folds the responses of a stream into a single response, shaped like the one of a call that
+/// isn't streamed.
+///
+/// For each candidate, consecutive text parts are concatenated and other parts, such as
+/// function calls, are kept in order. Citations are merged, the latest safety rating of each
+/// category and the latest finish reason are kept. The usage metadata is the one of the last
+/// response reporting it.
+#[derive(Clone, Debug, Default)]
+pub struct StreamAccumulator {
+    candidates: Vec<Candidate>,
+    usage_metadata: Option<UsageMetadata>,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        StreamAccumulator::default()
+    }
+
+    pub fn push(&mut self, response: GenerateContentResponseResult) {
+        for (position, chunk) in response.candidates.into_iter().enumerate() {
+            let index = candidate_index(position, &chunk);
+            if self.candidates.len() <= index {
+                self.candidates.resize_with(index + 1, Candidate::default);
+            }
+            merge_candidate(&mut self.candidates[index], chunk);
+        }
+        if response.usage_metadata.is_some() {
+            self.usage_metadata = response.usage_metadata;
+        }
+    }
+
+    /// The response folded so far.
+    pub fn response(&self) -> GenerateContentResponseResult {
+        self.clone().finish()
+    }
+
+    pub fn finish(self) -> GenerateContentResponseResult {
+        GenerateContentResponseResult {
+            candidates: self.candidates,
+            usage_metadata: self.usage_metadata,
+        }
+    }
+}
+
+fn merge_candidate(candidate: &mut Candidate, chunk: Candidate) {
+    candidate.index = candidate.index.or(chunk.index);
+    if let Some(content) = chunk.content {
+        merge_content(
+            candidate.content.get_or_insert_with(Content::default),
+            content,
+        );
+    }
+    if let Some(citation_metadata) = chunk.citation_metadata {
+        candidate
+            .citation_metadata
+            .get_or_insert_with(Default::default)
+            .citations
+            .extend(citation_metadata.citations);
+    }
+    if let Some(chunk_ratings) = chunk.safety_ratings {
+        let ratings = candidate.safety_ratings.get_or_insert_with(Vec::new);
+        for rating in chunk_ratings {
+            match ratings.iter_mut().find(|r| r.category == rating.category) {
+                Some(existing) => *existing = rating,
+                None => ratings.push(rating),
+            }
+        }
+    }
+    if chunk.finish_reason.is_some() {
+        candidate.finish_reason = chunk.finish_reason;
+    }
+}
+
+fn merge_content(content: &mut Content, chunk: Content) {
+    content.role = chunk.role.or(content.role);
+    let Some(chunk_parts) = chunk.parts else {
+        return;
+    };
+    let parts = content.parts.get_or_insert_with(Vec::new);
+    for part in chunk_parts {
+        match (parts.last_mut(), part) {
+            (Some(Part::Text(text)), Part::Text(delta)) => text.push_str(&delta),
+            (_, part) => parts.push(part),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::Instant;
+    use tokio_stream::StreamExt;
+
+    use super::ResponseStream;
+    use crate::error::{Error, Result};
+    use crate::prelude::{Part, StreamEvent};
+    use crate::timeout::{TimeoutStream, Timeouts};
+    use crate::types::{GenerateContentResponse, GenerateContentResponseResult};
+
+    fn response(json: &str) -> Result<GenerateContentResponseResult> {
+        serde_json::from_str::<GenerateContentResponse>(json)
+            .unwrap()
+            .into_result()
+    }
+
+    fn stream(responses: Vec<Result<GenerateContentResponseResult>>) -> ResponseStream {
+        let inner = Box::pin(tokio_stream::iter(responses));
+        ResponseStream::new(TimeoutStream::new(
+            inner,
+            Instant::now(),
+            &Timeouts::default(),
+        ))
+    }
+
+    #[tokio::test]
+    async fn ends_on_the_finish_reason_or_the_first_error() {
+        let finished = stream(vec![
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hi"}]}}]}"#,
+            ),
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "!"}]}, "finishReason": "STOP"}]}"#,
+            ),
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "?"}]}}]}"#,
+            ),
+        ]);
+        assert_eq!(finished.collect::<Vec<_>>().await.len(), 2);
+
+        let failed = stream(vec![
+            Err(Error::NoCandidatesError),
+            response(
+                r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hi"}]}}]}"#,
+            ),
+        ]);
+        let items = failed.collect::<Vec<_>>().await;
+        assert!(matches!(items[..], [Err(Error::NoCandidatesError)]));
+
+        let truncated = stream(vec![response(
+            r#"{"candidates": [{"content": {"role": "model","parts": [{"text": "Hi"}]}}]}"#,
+        )]);
+        let items = truncated.collect::<Vec<_>>().await;
+        assert!(matches!(
+            items[..],
+            [Ok(_), Err(Error::EventSourceClosedError)]
+        ));
+
+        // With several candidates, the stream goes on until all of them have finished.
+        let staggered = stream(vec![
+            response(r#"{"candidates": [{"index": 0, "finishReason": "STOP"}, {"index": 1}]}"#),
+            response(r#"{"candidates": [{"index": 1, "finishReason": "STOP"}]}"#),
+            response(r#"{"candidates": [{"index": 1}]}"#),
+        ]);
+        assert_eq!(staggered.collect::<Vec<_>>().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn collects_the_chunks_into_one_response() {
+        let response = stream(vec![
+            response(
+                r#"{"candidates": [
+                    {"index": 0, "content": {"role": "model", "parts": [{"text": "Hel"}]},
+                     "safetyRatings": [{"category": "HARM_CATEGORY_HARASSMENT", "probability": "LOW"}]},
+                    {"index": 1, "content": {"role": "model", "parts": [{"text": "Bye"}]}}
+                ]}"#,
+            ),
+            response(
+                r#"{"candidates": [
+                    {"index": 0, "content": {"role": "model", "parts": [
+                        {"text": "lo"},
+                        {"functionCall": {"name": "lookup", "args": {"city": "Paris"}}}
+                    ]},
+                    "citationMetadata": {"citations": [{"uri": "https://example.com"}]},
+                    "safetyRatings": [{"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"}],
+                    "finishReason": "STOP"},
+                    {"index": 1, "content": {"role": "model", "parts": [{"text": "!"}]}, "finishReason": "STOP"}
+                ],
+                "usageMetadata": {"promptTokenCount": 3, "candidatesTokenCount": 4, "totalTokenCount": 7}}"#,
+            ),
+        ])
+        .collect_response()
+        .await
+        .unwrap();
+
+        assert_eq!(response.candidates.len(), 2);
+        let first = &response.candidates[0];
+        let parts = first.content.as_ref().unwrap().parts.as_ref().unwrap();
+        assert!(
+            matches!(&parts[..], [Part::Text(text), Part::FunctionCall { name, .. }]
+            if text == "Hello" && name == "lookup")
+        );
+        assert_eq!(first.citation_metadata.as_ref().unwrap().citations.len(), 1);
+        let ratings = first.safety_ratings.as_ref().unwrap();
+        assert_eq!(ratings.len(), 1);
+        assert_eq!(ratings[0].probability, "NEGLIGIBLE");
+        assert_eq!(first.finish_reason.as_deref(), Some("STOP"));
+        assert_eq!(response.candidates[1].get_text().unwrap(), "Bye!");
+        assert_eq!(response.usage_metadata.unwrap().total_token_count, Some(7));
+    }
+
+    #[tokio::test]
+    async fn collects_candidates_finishing_in_different_chunks() {
+        let responses = || {
+            vec![
+                response(
+                    r#"{"candidates": [
+                        {"index": 0, "content": {"role": "model", "parts": [{"text": "Yes"}]}, "finishReason": "STOP"},
+                        {"index": 1, "content": {"role": "model", "parts": [{"text": "No"}]}}
+                    ]}"#,
+                ),
+                response(
+                    r#"{"candidates": [
+                        {"index": 1, "content": {"role": "model", "parts": [{"text": ", never"}]}, "finishReason": "STOP"}
+                    ],
+                    "usageMetadata": {"totalTokenCount": 5}}"#,
+                ),
+            ]
+        };
+
+        let response = stream(responses()).collect_response().await.unwrap();
+        assert_eq!(response.candidates[0].get_text().unwrap(), "Yes");
+        assert_eq!(response.candidates[1].get_text().unwrap(), "No, never");
+        assert_eq!(
+            response.candidates[1].finish_reason.as_deref(),
+            Some("STOP")
+        );
+
+        let events: Vec<_> = stream(responses())
+            .candidate_events(1)
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert!(matches!(
+            &events[..],
+            [
+                StreamEvent::TextDelta(no),
+                StreamEvent::TextDelta(never),
+                StreamEvent::Finished { reason, .. },
+            ] if no == "No" && never == ", never" && reason == "STOP"
+        ));
+    }
+}
@@ -0,0 +1,187 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tokio::time::{Instant, Sleep};
+use tokio_stream::Stream;
+
+use crate::error::{Error, Result};
+
+/// Deadlines for calls made by the client. `None` disables a timeout.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Timeouts {
+    /// Time allowed to establish a connection. Only applies to the HTTP client created by the
+    /// [`crate::prelude::GeminiClient`] itself, and can't be overridden per call.
+    pub connect: Option<Duration>,
+    /// Time allowed for the whole call, including retries and, for streams, reading every
+    /// event.
+    pub total: Option<Duration>,
+    /// Time allowed for a stream to deliver its first event.
+    pub first_token: Option<Duration>,
+    /// Time allowed between two events of a stream.
+    pub idle: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Returns these timeouts, falling back to `defaults` for the ones that aren't set.
+    pub(crate) fn or(self, defaults: Timeouts) -> Timeouts {
+        Timeouts {
+            connect: self.connect.or(defaults.connect),
+            total: self.total.or(defaults.total),
+            first_token: self.first_token.or(defaults.first_token),
+            idle: self.idle.or(defaults.idle),
+        }
+    }
+
+    /// The deadline for the first event of a stream started at `started`.
+    pub(crate) fn first_event_deadline(&self, started: Instant) -> Option<(Instant, TimeoutKind)> {
+        earliest(
+            self.first_token
+                .map(|first_token| (started + first_token, TimeoutKind::FirstToken)),
+            self.total
+                .map(|total| (started + total, TimeoutKind::Total)),
+        )
+    }
+}
+
+fn earliest(
+    a: Option<(Instant, TimeoutKind)>,
+    b: Option<(Instant, TimeoutKind)>,
+) -> Option<(Instant, TimeoutKind)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Which of the [`Timeouts`] expired.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutKind {
+    Connect,
+    Total,
+    FirstToken,
+    Idle,
+}
+
+impl Display for TimeoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self {
+            TimeoutKind::Connect => "connect",
+            TimeoutKind::Total => "total",
+            TimeoutKind::FirstToken => "time to first token",
+            TimeoutKind::Idle => "idle",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// Runs `future`, failing with [`Error::Timeout`] if `deadline` passes first.
+pub(crate) async fn with_deadline<F, R>(
+    deadline: Option<(Instant, TimeoutKind)>,
+    future: F,
+) -> Result<R>
+where
+    F: Future<Output = Result<R>>,
+{
+    match deadline {
+        Some((deadline, kind)) => tokio::time::timeout_at(deadline, future)
+            .await
+            .unwrap_or(Err(Error::Timeout(kind))),
+        None => future.await,
+    }
+}
+
+/// Enforces the stream related [`Timeouts`], ending the stream with an [`Error::Timeout`] when
+/// one of them expires.
+pub(crate) struct TimeoutStream<S> {
+    inner: S,
+    total_deadline: Option<Instant>,
+    first_token_deadline: Option<Instant>,
+    idle: Option<Duration>,
+    last_event: Option<Instant>,
+    sleep: Option<(Pin<Box<Sleep>>, TimeoutKind)>,
+    done: bool,
+}
+
+impl<S> TimeoutStream<S> {
+    /// Wraps a stream for a call started at `started`.
+    pub fn new(inner: S, started: Instant, timeouts: &Timeouts) -> Self {
+        let mut stream = TimeoutStream {
+            inner,
+            total_deadline: timeouts.total.map(|total| started + total),
+            first_token_deadline: timeouts
+                .first_token
+                .map(|first_token| started + first_token),
+            idle: timeouts.idle,
+            last_event: None,
+            sleep: None,
+            done: false,
+        };
+        stream.sleep = stream
+            .next_deadline()
+            .map(|(deadline, kind)| (Box::pin(tokio::time::sleep_until(deadline)), kind));
+        stream
+    }
+
+    /// The earliest deadline before the next event, if any.
+    fn next_deadline(&self) -> Option<(Instant, TimeoutKind)> {
+        let event_deadline = match self.last_event {
+            None => self
+                .first_token_deadline
+                .map(|deadline| (deadline, TimeoutKind::FirstToken)),
+            Some(last_event) => self.idle.map(|idle| (last_event + idle, TimeoutKind::Idle)),
+        };
+        let total_deadline = self
+            .total_deadline
+            .map(|deadline| (deadline, TimeoutKind::Total));
+        earliest(event_deadline, total_deadline)
+    }
+}
+
+impl<S, T> Stream for TimeoutStream<S>
+where
+    S: Stream<Item = Result<T>> + Unpin,
+{
+    type Item = Result<T>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+
+        match Pin::new(&mut self.inner).poll_next(cx) {
+            Poll::Ready(Some(item)) => {
+                self.last_event = Some(Instant::now());
+                match self.next_deadline() {
+                    Some((deadline, kind)) => match &mut self.sleep {
+                        Some((sleep, current_kind)) => {
+                            sleep.as_mut().reset(deadline);
+                            *current_kind = kind;
+                        }
+                        None => {
+                            self.sleep = Some((Box::pin(tokio::time::sleep_until(deadline)), kind))
+                        }
+                    },
+                    None => self.sleep = None,
+                }
+                return Poll::Ready(Some(item));
+            }
+            Poll::Ready(None) => {
+                self.done = true;
+                return Poll::Ready(None);
+            }
+            Poll::Pending => {}
+        }
+
+        if let Some((sleep, kind)) = &mut self.sleep {
+            if sleep.as_mut().poll(cx).is_ready() {
+                let kind = *kind;
+                self.done = true;
+                return Poll::Ready(Some(Err(Error::Timeout(kind))));
+            }
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::Result;
+use crate::token_provider::{AccessToken, TokenProvider};
+
+/// Counters describing the activity of a [`CachingTokenProvider`].
+#[derive(Clone, Debug, Default)]
+pub struct TokenCacheMetrics {
+    /// Tokens returned from the cache.
+    pub hits: u64,
+    /// Tokens fetched from the wrapped provider, in the background or not.
+    pub refreshes: u64,
+    pub refresh_failures: u64,
+    /// The error of the last failed refresh.
+    pub last_refresh_error: Option<String>,
+}
+
+/// Caches the tokens of another [`TokenProvider`] until they are about to expire.
+///
+/// A token is refreshed in the background once it gets within
+/// [`CachingTokenProvider::refresh_before`] of its expiry, while the cached token keeps being
+/// returned. Concurrent calls needing a new token share a single request to the wrapped
+/// provider. Tokens are cached per set of scopes.
+///
+/// The expiry of tokens is taken from [`TokenProvider::get_access_token`]. Tokens without one
+/// are kept for [`CachingTokenProvider::default_ttl`].
+///
+/// The provider is cheap to clone, and clones share their cache.
+pub struct CachingTokenProvider<T> {
+    provider: Arc<T>,
+    refresh_before: Duration,
+    default_ttl: Duration,
+    cache: Arc<Cache>,
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: Mutex<HashMap<Vec<String>, Arc<Entry>>>,
+    hits: AtomicU64,
+    refreshes: AtomicU64,
+    refresh_failures: AtomicU64,
+    last_refresh_error: Mutex<Option<String>>,
+}
+
+#[derive(Default)]
+struct Entry {
+    token: tokio::sync::Mutex<Option<CachedToken>>,
+    refreshing: AtomicBool,
+}
+
+#[derive(Clone)]
+struct CachedToken {
+    token: String,
+    expires_at: Instant,
+}
+
+impl<T> Clone for CachingTokenProvider<T> {
+    fn clone(&self) -> Self {
+        CachingTokenProvider {
+            provider: self.provider.clone(),
+            refresh_before: self.refresh_before,
+            default_ttl: self.default_ttl,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+impl<T> CachingTokenProvider<T> {
+    pub fn new(provider: T) -> Self {
+        CachingTokenProvider {
+            provider: Arc::new(provider),
+            refresh_before: Duration::from_secs(5 * 60),
+            default_ttl: Duration::from_secs(30 * 60),
+            cache: Arc::new(Cache::default()),
+        }
+    }
+
+    /// Sets how long before their expiry tokens are refreshed. Defaults to five minutes.
+    pub fn refresh_before(mut self, refresh_before: Duration) -> Self {
+        self.refresh_before = refresh_before;
+        self
+    }
+
+    /// Sets how long tokens without a known expiry are cached. Defaults to thirty minutes.
+    pub fn default_ttl(mut self, default_ttl: Duration) -> Self {
+        self.default_ttl = default_ttl;
+        self
+    }
+
+    pub fn metrics(&self) -> TokenCacheMetrics {
+        TokenCacheMetrics {
+            hits: self.cache.hits.load(Ordering::Relaxed),
+            refreshes: self.cache.refreshes.load(Ordering::Relaxed),
+            refresh_failures: self.cache.refresh_failures.load(Ordering::Relaxed),
+            last_refresh_error: self.cache.last_refresh_error.lock().unwrap().clone(),
+        }
+    }
+
+    fn entry(&self, scope: &[String]) -> Arc<Entry> {
+        let mut entries = self.cache.entries.lock().unwrap();
+        entries.entry(scope.to_vec()).or_default().clone()
+    }
+}
+
+impl<T: TokenProvider + Send + Sync + 'static> CachingTokenProvider<T> {
+    async fn fetch(&self, scope: &[&str]) -> Result<CachedToken> {
+        self.cache.refreshes.fetch_add(1, Ordering::Relaxed);
+        match self.provider.get_access_token(scope).await {
+            Ok(token) => Ok(self.to_cached(token)),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to refresh token");
+                self.cache.refresh_failures.fetch_add(1, Ordering::Relaxed);
+                *self.cache.last_refresh_error.lock().unwrap() = Some(e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    fn to_cached(&self, token: AccessToken) -> CachedToken {
+        let ttl = match token.expires_at {
+            Some(expires_at) => expires_at
+                .duration_since(SystemTime::now())
+                .unwrap_or_default(),
+            None => self.default_ttl,
+        };
+        CachedToken {
+            token: token.token,
+            expires_at: Instant::now() + ttl,
+        }
+    }
+
+    /// Refreshes the token of `entry` in a background task, unless a refresh is running.
+    fn refresh_in_background(&self, entry: Arc<Entry>, scope: Vec<String>) {
+        if entry.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let this = self.clone();
+        tokio::spawn(async move {
+            let scope: Vec<&str> = scope.iter().map(String::as_str).collect();
+            if let Ok(token) = this.fetch(&scope).await {
+                *entry.token.lock().await = Some(token);
+            }
+            entry.refreshing.store(false, Ordering::Release);
+        });
+    }
+}
+
+impl<T: TokenProvider + Send + Sync + 'static> TokenProvider for CachingTokenProvider<T> {
+    fn get_token(&self, scope: &[&str]) -> impl Future<Output = Result<String>> + Send {
+        let scope: Vec<String> = scope.iter().map(|scope| scope.to_string()).collect();
+        async move {
+            let entry = self.entry(&scope);
+            // Callers needing a new token wait on the lock for the one fetching it.
+            let mut cached = entry.token.lock().await;
+            if let Some(token) = cached.clone() {
+                let now = Instant::now();
+                if now < token.expires_at {
+                    self.cache.hits.fetch_add(1, Ordering::Relaxed);
+                    drop(cached);
+                    if now + self.refresh_before >= token.expires_at {
+                        self.refresh_in_background(entry, scope);
+                    }
+                    return Ok(token.token);
+                }
+            }
+
+            let scope: Vec<&str> = scope.iter().map(String::as_str).collect();
+            let token = self.fetch(&scope).await?;
+            *cached = Some(token.clone());
+            Ok(token.token)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+
+    use super::CachingTokenProvider;
+    use crate::error::{Error, Result};
+    use crate::token_provider::{AccessToken, TokenProvider};
+
+    /// Returns numbered tokens valid for `ttl`, or fails once `fail` is set.
+    #[derive(Default)]
+    struct CountingProvider {
+        calls: Arc<AtomicU32>,
+        ttl: Duration,
+        fail: bool,
+    }
+
+    impl TokenProvider for CountingProvider {
+        async fn get_token(&self, scope: &[&str]) -> Result<String> {
+            Ok(self.get_access_token(scope).await?.token)
+        }
+
+        async fn get_access_token(&self, _scope: &[&str]) -> Result<AccessToken> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst) + 1;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            if self.fail {
+                return Err(Error::Credentials("refresh failed".to_string()));
+            }
+            Ok(AccessToken {
+                token: format!("token-{call}"),
+                expires_at: Some(SystemTime::now() + self.ttl),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn shares_fetches_and_refreshes_in_the_background() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let provider = CachingTokenProvider::new(CountingProvider {
+            calls: calls.clone(),
+            ttl: Duration::from_secs(60),
+            fail: false,
+        })
+        .refresh_before(Duration::from_secs(30));
+
+        let tokens = tokio::join!(
+            provider.get_token(&["scope"]),
+            provider.get_token(&["scope"]),
+            provider.get_token(&["scope"]),
+        );
+        assert_eq!(tokens.0.unwrap(), "token-1");
+        assert_eq!(tokens.2.unwrap(), "token-1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Within the refresh window, the cached token is returned while a new one is fetched.
+        let provider = provider.refresh_before(Duration::from_secs(90));
+        assert_eq!(provider.get_token(&["scope"]).await.unwrap(), "token-1");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(provider.get_token(&["scope"]).await.unwrap(), "token-2");
+
+        let metrics = provider.metrics();
+        assert_eq!(metrics.hits, 4);
+        assert!(metrics.refreshes >= 2);
+    }
+
+    #[tokio::test]
+    async fn records_refresh_failures() {
+        let provider = CachingTokenProvider::new(CountingProvider {
+            fail: true,
+            ..Default::default()
+        });
+
+        assert!(provider.get_token(&["scope"]).await.is_err());
+        let metrics = provider.metrics();
+        assert_eq!(metrics.refresh_failures, 1);
+        assert!(metrics.last_refresh_error.is_some());
+    }
+}
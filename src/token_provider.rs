@@ -1,10 +1,33 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use crate::error::Result;
 
 pub trait TokenProvider {
-    fn get_token(&self, scope: &[&str])
-        -> impl std::future::Future<Output = Result<String>> + Send;
+    fn get_token(&self, scope: &[&str]) -> impl Future<Output = Result<String>> + Send;
+
+    /// Returns a token along with its expiry. Providers that know when their tokens expire
+    /// should implement this, so [`crate::prelude::CachingTokenProvider`] can refresh them in
+    /// time. By default, the expiry is unknown.
+    fn get_access_token(&self, scope: &[&str]) -> impl Future<Output = Result<AccessToken>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            Ok(AccessToken {
+                token: self.get_token(scope).await?,
+                expires_at: None,
+            })
+        }
+    }
+}
+
+/// A token returned by a [`TokenProvider`].
+#[derive(Clone, Debug)]
+pub struct AccessToken {
+    pub token: String,
+    pub expires_at: Option<SystemTime>,
 }
 
 impl TokenProvider for Arc<dyn gcp_auth::TokenProvider + '_> {
@@ -15,4 +38,81 @@ impl TokenProvider for Arc<dyn gcp_auth::TokenProvider + '_> {
             Err(e) => Err(e.into()),
         }
     }
+
+    // `async fn` can't infer the lifetime of the trait object here.
+    #[allow(clippy::manual_async_fn)]
+    fn get_access_token(&self, scope: &[&str]) -> impl Future<Output = Result<AccessToken>> + Send
+    where
+        Self: Sync,
+    {
+        async move {
+            let token = self.token(scope).await?;
+            Ok(AccessToken {
+                token: token.as_str().to_string(),
+                expires_at: Some(SystemTime::from(token.expires_at())),
+            })
+        }
+    }
+}
+
+/// An API key for Google AI Studio, created in the AI Studio console, or for Vertex AI in
+/// express mode.
+#[derive(Clone, Debug)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new<T: Into<String>>(api_key: T) -> Self {
+        ApiKey(api_key.into())
+    }
+}
+
+impl TokenProvider for ApiKey {
+    async fn get_token(&self, _scope: &[&str]) -> Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A fixed OAuth access token, e.g. from `gcloud auth print-access-token`, returned for every
+/// scope. The token isn't refreshed, so calls fail once it expires, typically after an hour.
+#[derive(Clone, Debug)]
+pub struct StaticToken(String);
+
+impl StaticToken {
+    pub fn new<T: Into<String>>(token: T) -> Self {
+        StaticToken(token.into())
+    }
+}
+
+impl TokenProvider for StaticToken {
+    async fn get_token(&self, _scope: &[&str]) -> Result<String> {
+        Ok(self.0.clone())
+    }
+}
+
+/// A token provider calling a closure with the requested scopes, e.g. to read a token from a
+/// secret store or to return canned tokens in tests.
+///
+/// ```
+/// # use gemini_rs::prelude::*;
+/// let token_provider = FnTokenProvider::new(|_scope: &[&str]| async {
+///     std::env::var("ACCESS_TOKEN").map_err(gemini_rs::error::Error::from)
+/// });
+/// ```
+#[derive(Clone)]
+pub struct FnTokenProvider<F>(F);
+
+impl<F> FnTokenProvider<F> {
+    pub fn new(get_token: F) -> Self {
+        FnTokenProvider(get_token)
+    }
+}
+
+impl<F, Fut> TokenProvider for FnTokenProvider<F>
+where
+    F: Fn(&[&str]) -> Fut,
+    Fut: Future<Output = Result<String>> + Send,
+{
+    fn get_token(&self, scope: &[&str]) -> impl Future<Output = Result<String>> + Send {
+        (self.0)(scope)
+    }
 }
@@ -13,6 +13,20 @@ impl CountTokensRequest {
     }
 }
 
+/// The `countTokens` request body expected by Google AI Studio, which takes a list of contents.
+#[derive(Serialize)]
+pub(crate) struct AiStudioCountTokensRequest<'a> {
+    pub contents: Vec<&'a Content>,
+}
+
+impl<'a> From<&'a CountTokensRequest> for AiStudioCountTokensRequest<'a> {
+    fn from(request: &'a CountTokensRequest) -> Self {
+        AiStudioCountTokensRequest {
+            contents: vec![&request.contents],
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct CountTokensRequestBuilder {
     contents: Content,
@@ -41,6 +55,8 @@ pub enum CountTokensResponse {
     #[serde(rename_all = "camelCase")]
     Ok {
         total_tokens: i32,
+        /// Not reported by Google AI Studio, where it defaults to 0.
+        #[serde(default)]
         total_billable_characters: u32,
     },
     Error {
@@ -39,6 +39,16 @@ pub enum ErrorType {
         #[serde(rename = "fieldViolations")]
         field_violations: Vec<FieldViolation>,
     },
+
+    #[serde(rename = "type.googleapis.com/google.rpc.RetryInfo")]
+    RetryInfo {
+        #[serde(rename = "retryDelay")]
+        retry_delay: String,
+    },
+
+    /// Any detail type not modelled above.
+    #[serde(other)]
+    Other,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +18,9 @@ pub struct GenerateContentRequest {
     pub safety_settings: Option<Vec<SafetySetting>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub system_instruction: Option<Content>,
+    /// Labels for billing and cost attribution. Only supported by Vertex AI.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub labels: Option<HashMap<String, String>>,
 }
 
 impl GenerateContentRequest {
@@ -62,6 +65,14 @@ impl GenerateContentRequestBuilder {
         self
     }
 
+    pub fn label<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        self.request
+            .labels
+            .get_or_insert_with(HashMap::new)
+            .insert(key.into(), value.into());
+        self
+    }
+
     pub fn build(self) -> GenerateContentRequest {
         self.request
     }
@@ -236,9 +247,12 @@ pub enum HarmBlockMethod {
     Probability, // PROBABILITY
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Candidate {
+    /// The position of the candidate, set when several candidates are requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub content: Option<Content>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -258,7 +272,7 @@ impl Candidate {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Citation {
     pub start_index: Option<i32>,
@@ -266,27 +280,35 @@ pub struct Citation {
     pub uri: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct CitationMetadata {
     pub citations: Vec<Citation>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SafetyRating {
     pub category: String,
     pub probability: String,
     pub probability_score: Option<f32>,
-    pub severity: String,
+    /// Not reported by Google AI Studio.
+    pub severity: Option<String>,
     pub severity_score: Option<f32>,
+    /// Whether the content was blocked because of this rating.
+    pub blocked: Option<bool>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct UsageMetadata {
     pub candidates_token_count: Option<u32>,
     pub prompt_token_count: Option<u32>,
     pub total_token_count: Option<u32>,
+    /// The part of the prompt read from the context cache, included in the prompt count.
+    pub cached_content_token_count: Option<u32>,
+    /// Tokens used by thinking models for their thoughts, not included in the candidates
+    /// count.
+    pub thoughts_token_count: Option<u32>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -328,10 +350,11 @@ impl From<GenerateContentResponse> for Result<GenerateContentResponseResult> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GenerateContentResponseResult {
     pub candidates: Vec<Candidate>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub usage_metadata: Option<UsageMetadata>,
 }
 
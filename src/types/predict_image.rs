@@ -96,9 +96,9 @@ pub struct PredictImageRequestParameters {
     /// Optional. The language code that corresponds to your text prompt language.
     /// The following values are supported:
     ///  - auto: Automatic detection. If Imagen detects a supported language, the prompt and an
-    ///          optional negative prompt are translated to English. If the language detected isn't
-    ///          supported, Imagen uses the input text verbatim, which might result in an unexpected
-    ///          output. No error code is returned.
+    ///    optional negative prompt are translated to English. If the language detected isn't
+    ///    supported, Imagen uses the input text verbatim, which might result in an unexpected
+    ///    output. No error code is returned.
     ///  - en: English (if omitted, the default value)
     ///  - zh or zh-CN: Chinese (simplified)
     ///  - zh-TW: Chinese (traditional)
@@ -113,9 +113,9 @@ pub struct PredictImageRequestParameters {
     /// Adds a filter level to safety filtering. The following values are supported:
     ///
     /// - "block_low_and_above": Strongest filtering level, most strict blocking.
-    ///    Deprecated value: "block_most".
+    ///   Deprecated value: "block_most".
     /// - "block_medium_and_above": Block some problematic prompts and responses.
-    ///    Deprecated value: "block_some".
+    ///   Deprecated value: "block_some".
     /// - "block_only_high": Reduces the number of requests blocked due to safety filters. May
     ///   increase objectionable content generated by Imagen. Deprecated value: "block_few".
     /// - "block_none": Block very few problematic prompts and responses. Access to this feature
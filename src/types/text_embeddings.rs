@@ -1,8 +1,9 @@
 use serde::{Deserialize, Serialize};
 
 use crate::error::{Error, Result};
+use crate::model::{Model, ModelName};
 
-use super::VertexApiError;
+use super::{Content, Part, VertexApiError};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TextEmbeddingRequest {
@@ -50,12 +51,87 @@ pub struct TextEmbeddingPrediction {
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TextEmbeddingResult {
+    /// Not reported by Google AI Studio, where it is left at its default value.
+    #[serde(default)]
     pub statistics: TextEmbeddingStatistics,
     pub values: Vec<f64>,
 }
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct TextEmbeddingStatistics {
     pub truncated: bool,
     pub token_count: u32,
 }
+
+/// The `batchEmbedContents` request body used by Google AI Studio in place of `predict`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BatchEmbedContentsRequest {
+    pub requests: Vec<EmbedContentRequest>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct EmbedContentRequest {
+    pub model: String,
+    pub content: Content,
+    pub task_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+impl BatchEmbedContentsRequest {
+    pub fn new(request: &TextEmbeddingRequest, model: &Model) -> Self {
+        let model = match model.name() {
+            ModelName::Id(id) => format!("models/{}", id),
+            _ => model.to_string(),
+        };
+        let requests = request
+            .instances
+            .iter()
+            .map(|instance| EmbedContentRequest {
+                model: model.clone(),
+                content: Content {
+                    role: None,
+                    parts: Some(vec![Part::Text(instance.content.clone())]),
+                },
+                task_type: instance.task_type.clone(),
+                title: instance.title.clone(),
+            })
+            .collect();
+        BatchEmbedContentsRequest { requests }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub(crate) enum BatchEmbedContentsResponse {
+    Ok { embeddings: Vec<ContentEmbedding> },
+    Error { error: VertexApiError },
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ContentEmbedding {
+    pub values: Vec<f64>,
+}
+
+impl From<BatchEmbedContentsResponse> for TextEmbeddingResponse {
+    fn from(value: BatchEmbedContentsResponse) -> Self {
+        match value {
+            BatchEmbedContentsResponse::Ok { embeddings } => {
+                TextEmbeddingResponse::Ok(TextEmbeddingResponseOk {
+                    predictions: embeddings
+                        .into_iter()
+                        .map(|embedding| TextEmbeddingPrediction {
+                            embeddings: TextEmbeddingResult {
+                                statistics: TextEmbeddingStatistics::default(),
+                                values: embedding.values,
+                            },
+                        })
+                        .collect(),
+                })
+            }
+            BatchEmbedContentsResponse::Error { error } => TextEmbeddingResponse::Error { error },
+        }
+    }
+}
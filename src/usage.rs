@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::types::UsageMetadata;
+
+/// Token counts summed over calls, with their estimated cost.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TokenUsage {
+    pub calls: u64,
+    /// Prompt tokens, including the cached ones.
+    pub prompt_tokens: u64,
+    pub candidates_tokens: u64,
+    pub cached_tokens: u64,
+    pub thoughts_tokens: u64,
+    pub total_tokens: u64,
+    /// The estimated cost in US dollars of the calls to models in the [`PriceTable`].
+    pub cost: f64,
+}
+
+impl TokenUsage {
+    fn from_metadata(usage: &UsageMetadata) -> Self {
+        let count = |count: Option<u32>| count.unwrap_or_default() as u64;
+        TokenUsage {
+            calls: 1,
+            prompt_tokens: count(usage.prompt_token_count),
+            candidates_tokens: count(usage.candidates_token_count),
+            cached_tokens: count(usage.cached_content_token_count),
+            thoughts_tokens: count(usage.thoughts_token_count),
+            total_tokens: count(usage.total_token_count),
+            cost: 0.0,
+        }
+    }
+
+    fn add(&mut self, other: &TokenUsage) {
+        self.calls += other.calls;
+        self.prompt_tokens += other.prompt_tokens;
+        self.candidates_tokens += other.candidates_tokens;
+        self.cached_tokens += other.cached_tokens;
+        self.thoughts_tokens += other.thoughts_tokens;
+        self.total_tokens += other.total_tokens;
+        self.cost += other.cost;
+    }
+}
+
+/// The prices of a model, in US dollars per million tokens.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    /// The price of prompt tokens read from the context cache.
+    pub cached_input: f64,
+    /// The price of candidates and thoughts tokens.
+    pub output: f64,
+}
+
+impl ModelPrice {
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        let uncached_tokens = usage.prompt_tokens.saturating_sub(usage.cached_tokens);
+        let output_tokens = usage.candidates_tokens + usage.thoughts_tokens;
+        (uncached_tokens as f64 * self.input
+            + usage.cached_tokens as f64 * self.cached_input
+            + output_tokens as f64 * self.output)
+            / 1_000_000.0
+    }
+}
+
+/// The prices used by a [`UsageTracker`] to estimate costs.
+///
+/// A model gets the price of the longest name in the table it starts with, so a price for
+/// `gemini-2.0-flash` also applies to `gemini-2.0-flash-001`. Models are named as in
+/// [`UsageTracker::by_model`].
+#[derive(Clone, Debug, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        PriceTable::default()
+    }
+
+    pub fn with_price<M: Into<String>>(mut self, model: M, price: ModelPrice) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    pub fn price(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(name, _)| model.starts_with(name.as_str()))
+            .max_by_key(|(name, _)| name.len())
+            .map(|(_, price)| price)
+    }
+}
+
+/// Accounts for the tokens used by content generation calls, per model and per caller tag.
+///
+/// Given to [`crate::prelude::GeminiClientBuilder::usage_tracker`], it records the
+/// `UsageMetadata` of every call, streamed or not, under the tag set with
+/// [`crate::prelude::RequestOptionsBuilder::usage_tag`].
+///
+/// The tracker is cheap to clone, and clones share their state, so one tracker can be shared
+/// by several clients.
+#[derive(Clone, Debug, Default)]
+pub struct UsageTracker {
+    prices: PriceTable,
+    state: Arc<Mutex<UsageState>>,
+}
+
+#[derive(Debug, Default)]
+struct UsageState {
+    total: TokenUsage,
+    by_model: HashMap<String, TokenUsage>,
+    by_tag: HashMap<String, TokenUsage>,
+}
+
+impl UsageTracker {
+    pub fn new(prices: PriceTable) -> Self {
+        UsageTracker {
+            prices,
+            state: Arc::default(),
+        }
+    }
+
+    /// Records the usage of a call to `model`, returning it along with its estimated cost.
+    pub fn record(&self, model: &str, tag: Option<&str>, usage: &UsageMetadata) -> TokenUsage {
+        let mut call = TokenUsage::from_metadata(usage);
+        if let Some(price) = self.prices.price(model) {
+            call.cost = price.cost(&call);
+        }
+
+        let mut state = self.state.lock().unwrap();
+        state.total.add(&call);
+        state
+            .by_model
+            .entry(model.to_string())
+            .or_default()
+            .add(&call);
+        if let Some(tag) = tag {
+            state.by_tag.entry(tag.to_string()).or_default().add(&call);
+        }
+        call
+    }
+
+    pub fn total(&self) -> TokenUsage {
+        self.state.lock().unwrap().total
+    }
+
+    /// The usage per model, named as in the request URLs: `gemini-2.0-flash-001` or
+    /// `publishers/{publisher}/models/{id}` for publisher models.
+    pub fn by_model(&self) -> HashMap<String, TokenUsage> {
+        self.state.lock().unwrap().by_model.clone()
+    }
+
+    /// The usage per caller tag. Untagged calls are only counted per model and in the total.
+    pub fn by_tag(&self) -> HashMap<String, TokenUsage> {
+        self.state.lock().unwrap().by_tag.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ModelPrice, PriceTable, UsageTracker};
+    use crate::types::UsageMetadata;
+
+    #[test]
+    fn aggregates_usage_and_costs() {
+        let prices = PriceTable::new()
+            .with_price(
+                "gemini-2.0-flash",
+                ModelPrice {
+                    input: 0.1,
+                    cached_input: 0.025,
+                    output: 0.4,
+                },
+            )
+            .with_price("gemini-2.0-flash-lite", ModelPrice::default());
+        let tracker = UsageTracker::new(prices);
+        let usage = UsageMetadata {
+            prompt_token_count: Some(1_000_000),
+            cached_content_token_count: Some(400_000),
+            candidates_token_count: Some(500_000),
+            thoughts_token_count: Some(500_000),
+            total_token_count: Some(2_000_000),
+        };
+
+        let call = tracker.record("gemini-2.0-flash-001", Some("search"), &usage);
+        assert!((call.cost - (0.06 + 0.01 + 0.4)).abs() < 1e-9);
+        tracker.record("gemini-2.0-flash-lite-001", Some("search"), &usage);
+        tracker.record("gemini-1.5-pro-002", None, &usage);
+
+        let total = tracker.total();
+        assert_eq!(total.calls, 3);
+        assert_eq!(total.thoughts_tokens, 1_500_000);
+        assert!((total.cost - call.cost).abs() < 1e-9);
+        assert_eq!(tracker.by_model().len(), 3);
+        let by_tag = tracker.by_tag();
+        assert_eq!(by_tag["search"].calls, 2);
+        assert_eq!(by_tag["search"].cached_tokens, 800_000);
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use serde::Deserialize;
+
+use crate::client::AUTH_SCOPE;
+use crate::error::{Error, HttpStatusError, Result};
+use crate::iam::generate_access_token;
+use crate::token_provider::{AccessToken, TokenProvider};
+
+const TOKEN_EXCHANGE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
+/// A workload identity federation credential configuration, as created by
+/// `gcloud iam workload-identity-pools create-cred-config`.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ExternalAccountConfig {
+    pub audience: String,
+    pub subject_token_type: String,
+    pub token_url: String,
+    #[serde(default)]
+    pub service_account_impersonation_url: Option<String>,
+    #[serde(default)]
+    pub service_account_impersonation: Option<ServiceAccountImpersonation>,
+    pub credential_source: CredentialSource,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ServiceAccountImpersonation {
+    pub token_lifetime_seconds: Option<u64>,
+}
+
+/// Where the token of the external identity is read from. File and URL sources are
+/// supported.
+#[derive(Clone, Debug, Deserialize)]
+pub struct CredentialSource {
+    pub file: Option<String>,
+    pub url: Option<String>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    pub format: Option<CredentialFormat>,
+    /// Set for AWS sources, which aren't supported.
+    pub environment_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum CredentialFormat {
+    Text,
+    Json { subject_token_field_name: String },
+}
+
+#[derive(Deserialize)]
+struct StsResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Exchanges the token of an external identity, such as the OIDC token of a CI job, for a
+/// Google access token with workload identity federation, impersonating a service account if
+/// the configuration says so.
+#[derive(Clone, Debug)]
+pub struct WorkloadIdentityTokenProvider {
+    config: ExternalAccountConfig,
+    client: reqwest::Client,
+}
+
+impl WorkloadIdentityTokenProvider {
+    pub fn new(config: ExternalAccountConfig) -> Self {
+        WorkloadIdentityTokenProvider {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(WorkloadIdentityTokenProvider::new(serde_json::from_str(
+            json,
+        )?))
+    }
+
+    /// Reads the configuration from a file, such as the one pointed to by
+    /// `GOOGLE_APPLICATION_CREDENTIALS`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .map_err(|e| Error::Credentials(format!("failed to read {}: {}", path.display(), e)))?;
+        WorkloadIdentityTokenProvider::from_json(&json)
+    }
+
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Reads the token of the external identity from the credential source.
+    async fn subject_token(&self) -> Result<String> {
+        let source = &self.config.credential_source;
+        if let Some(environment_id) = &source.environment_id {
+            return Err(Error::Credentials(format!(
+                "{} credential sources are not supported",
+                environment_id
+            )));
+        }
+        let content = match (&source.file, &source.url) {
+            (Some(file), _) => tokio::fs::read_to_string(file)
+                .await
+                .map_err(|e| Error::Credentials(format!("failed to read {}: {}", file, e)))?,
+            (None, Some(url)) => {
+                let mut request = self.client.get(url);
+                for (name, value) in &source.headers {
+                    request = request.header(name, value);
+                }
+                let resp = request.send().await?;
+                if !resp.status().is_success() {
+                    return Err(HttpStatusError::from_response(resp).await.into());
+                }
+                resp.text().await?
+            }
+            (None, None) => {
+                return Err(Error::Credentials(
+                    "only file and URL credential sources are supported".to_string(),
+                ))
+            }
+        };
+
+        match &source.format {
+            None | Some(CredentialFormat::Text) => Ok(content.trim().to_string()),
+            Some(CredentialFormat::Json {
+                subject_token_field_name,
+            }) => {
+                let json: serde_json::Value = serde_json::from_str(&content)?;
+                json[subject_token_field_name]
+                    .as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| {
+                        Error::Credentials(format!(
+                            "missing {} in the credential source",
+                            subject_token_field_name
+                        ))
+                    })
+            }
+        }
+    }
+
+    async fn fetch(&self, scope: &[&str]) -> Result<AccessToken> {
+        let subject_token = self.subject_token().await?;
+        let impersonation_url = self.config.service_account_impersonation_url.as_ref();
+        // The federated token only needs to be able to impersonate the service account.
+        let sts_scope = match impersonation_url {
+            Some(_) => AUTH_SCOPE.join(" "),
+            None => scope.join(" "),
+        };
+        let form = [
+            ("grant_type", TOKEN_EXCHANGE_GRANT_TYPE),
+            ("audience", &self.config.audience),
+            ("scope", &sts_scope),
+            ("requested_token_type", ACCESS_TOKEN_TYPE),
+            ("subject_token", &subject_token),
+            ("subject_token_type", &self.config.subject_token_type),
+        ];
+        let resp = self
+            .client
+            .post(&self.config.token_url)
+            .form(&form)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            return Err(HttpStatusError::from_response(resp).await.into());
+        }
+        let sts: StsResponse = serde_json::from_str(&resp.text().await?)?;
+
+        match impersonation_url {
+            Some(url) => {
+                let lifetime = self
+                    .config
+                    .service_account_impersonation
+                    .as_ref()
+                    .and_then(|impersonation| impersonation.token_lifetime_seconds)
+                    .unwrap_or(3600);
+                let lifetime = Duration::from_secs(lifetime);
+                generate_access_token(&self.client, url, &sts.access_token, &[], scope, lifetime)
+                    .await
+            }
+            None => Ok(AccessToken {
+                token: sts.access_token,
+                expires_at: Some(SystemTime::now() + Duration::from_secs(sts.expires_in)),
+            }),
+        }
+    }
+}
+
+impl TokenProvider for WorkloadIdentityTokenProvider {
+    async fn get_token(&self, scope: &[&str]) -> Result<String> {
+        Ok(self.fetch(scope).await?.token)
+    }
+
+    async fn get_access_token(&self, scope: &[&str]) -> Result<AccessToken> {
+        self.fetch(scope).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WorkloadIdentityTokenProvider;
+    use crate::test_support::{MockResponse, MockServer};
+    use crate::token_provider::TokenProvider;
+
+    #[tokio::test]
+    async fn exchanges_the_subject_token_and_impersonates() {
+        let server = MockServer::start(vec![
+            MockResponse::json(200, r#"{"id_token": "oidc-token"}"#),
+            MockResponse::json(
+                200,
+                r#"{"access_token": "federated", "expires_in": 3600, "token_type": "Bearer"}"#,
+            ),
+            MockResponse::json(
+                200,
+                r#"{"accessToken": "impersonated", "expireTime": "2030-01-01T00:00:00Z"}"#,
+            ),
+        ])
+        .await;
+        let config = serde_json::json!({
+            "type": "external_account",
+            "audience": "//iam.googleapis.com/projects/1/locations/global/workloadIdentityPools/ci/providers/oidc",
+            "subject_token_type": "urn:ietf:params:oauth:token-type:jwt",
+            "token_url": format!("{}/v1/token", server.url()),
+            "service_account_impersonation_url": format!(
+                "{}/v1/projects/-/serviceAccounts/sa@p.iam.gserviceaccount.com:generateAccessToken",
+                server.url()
+            ),
+            "credential_source": {
+                "url": format!("{}/token", server.url()),
+                "headers": {"Metadata": "True"},
+                "format": {"type": "json", "subject_token_field_name": "id_token"},
+            },
+        });
+        let provider = WorkloadIdentityTokenProvider::from_json(&config.to_string()).unwrap();
+
+        let token = provider.get_token(&["scope"]).await.unwrap();
+        assert_eq!(token, "impersonated");
+
+        let requests = server.requests();
+        assert_eq!(requests[0].header("metadata"), Some("True"));
+        assert_eq!(requests[1].path, "/v1/token");
+        assert!(requests[1].body.contains("subject_token=oidc-token"));
+        assert!(requests[1]
+            .body
+            .contains("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Atoken-exchange"));
+        assert_eq!(
+            requests[2].header("authorization"),
+            Some("Bearer federated")
+        );
+        assert!(requests[2].body.contains(r#""scope":["scope"]"#));
+    }
+}